@@ -0,0 +1,85 @@
+//! Frame times of the sand scene against the number of grains, while they
+//! fall and once they've settled.
+//!
+//! ```text
+//! cargo bench --bench sand
+//! ```
+
+use std::time;
+
+use matryx_generator::scenes::SandScene;
+use matryx_generator::{Canvas, FrameTick, Scene};
+
+/// Canvas sizes to measure, from a single panel up.
+const SIZES: [(u32, u32); 3] = [(64, 32), (128, 64), (256, 128)];
+/// Frames averaged over for each measurement.
+const FRAMES: u32 = 30;
+
+/// Average milliseconds and moves per frame.
+struct Timing {
+    ms: f64,
+    moves: u64,
+}
+
+fn main() {
+    for &(width, height) in SIZES.iter() {
+        println!("{}x{}", width, height);
+        println!("grains\tfalling ms\tmoves\tsettled ms\tmoves");
+
+        // Fill a tenth of the canvas more each run.
+        for fill in 1..=9 {
+            let grains = (width * height) as u64 * fill / 10;
+            let (falling, settled) = run(width, height, grains);
+            println!(
+                "{}\t{:.3}\t{}\t{:.3}\t{}",
+                grains, falling.ms, falling.moves, settled.ms, settled.moves
+            );
+        }
+
+        println!();
+    }
+}
+
+/// Pours `grains` grains and times frames while they fall and once they've
+/// come to rest.
+fn run(width: u32, height: u32, grains: u64) -> (Timing, Timing) {
+    let mut canvas = Canvas::new(width, height);
+    let mut scene = SandScene::new(&canvas);
+    let tick = FrameTick {
+        dt: 0.1,
+        ..FrameTick::from_start()
+    };
+
+    scene.set_param("spout_width", 1.0);
+    scene.set_param("spout_rate", 300.0);
+    while scene.metrics().grains() < grains {
+        scene.tick(&mut canvas, &tick);
+    }
+    scene.set_param("spout_rate", 0.0);
+
+    let falling = time_frames(&mut scene, &mut canvas, &tick);
+
+    for _ in 0..height * 4 {
+        let moves = scene.metrics().moves;
+        scene.tick(&mut canvas, &tick);
+        if scene.metrics().moves == moves {
+            break;
+        }
+    }
+
+    let settled = time_frames(&mut scene, &mut canvas, &tick);
+    (falling, settled)
+}
+
+fn time_frames(scene: &mut SandScene, canvas: &mut Canvas, tick: &FrameTick) -> Timing {
+    let moves = scene.metrics().moves;
+    let start = time::Instant::now();
+    for _ in 0..FRAMES {
+        scene.tick(canvas, tick);
+    }
+
+    Timing {
+        ms: start.elapsed().as_secs_f64() * 1000.0 / FRAMES as f64,
+        moves: (scene.metrics().moves - moves) / FRAMES as u64,
+    }
+}
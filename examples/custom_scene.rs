@@ -0,0 +1,55 @@
+//! A binary with a scene of its own, drawn on a single 64x32 panel.
+//!
+//! ```text
+//! cargo run --example custom_scene
+//! ```
+
+use matryx_generator::mapping::Display;
+use matryx_generator::output::Output;
+use matryx_generator::params::Param;
+use matryx_generator::{Canvas, FrameTick, FrameTimer, Scene};
+
+/// A vertical bar sweeping across the canvas.
+struct Sweep {
+    speed: f32,
+}
+
+impl Scene for Sweep {
+    fn name(&self) -> &str {
+        "sweep"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![Param::new("speed", 0.1, 4.0, self.speed)]
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        if name == "speed" {
+            self.speed = value;
+        }
+    }
+
+    fn tick(&mut self, canvas: &mut Canvas, tick: &FrameTick) {
+        let x = (tick.t * self.speed * canvas.width() as f32) as u32 % canvas.width();
+
+        canvas.clear();
+        for y in 0..canvas.height() {
+            canvas.set_pixel(x, y, 1.0, 0.5, 0.0);
+        }
+    }
+}
+
+fn main() {
+    let display = Display::single("tcp://localhost:42024", 64, 32);
+    let mut output = Output::new(&display);
+    let mut canvas = Canvas::new(display.canvas_width, display.canvas_height);
+    let mut timer = FrameTimer::new();
+    let mut scene = Sweep { speed: 0.5 };
+
+    loop {
+        let tick = timer.tick();
+        scene.tick(&mut canvas, &tick);
+        output.send(&canvas);
+        timer.wait_for_next_frame();
+    }
+}
@@ -0,0 +1,488 @@
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time;
+
+pub const BANDS: usize = 8;
+
+const FFT_SIZE: usize = 1024;
+const HOP_SIZE: usize = 512;
+
+/// Lowest and highest frequencies covered by the bands, in Hz. Bands are
+/// spaced logarithmically in between.
+const MIN_FREQ: f32 = 40.0;
+const MAX_FREQ: f32 = 16000.0;
+
+/// How many analysis frames of spectral flux are used to decide whether the
+/// current one is an onset.
+const FLUX_HISTORY: usize = 43;
+const ONSET_THRESHOLD: f32 = 1.5;
+const MIN_BEAT_INTERVAL: time::Duration = time::Duration::from_millis(250);
+
+const ENVELOPE_ATTACK: f32 = 0.6;
+const ENVELOPE_RELEASE: f32 = 0.08;
+
+/// Audio analysis results, as seen by scenes through [`crate::FrameTick`].
+///
+/// Levels are roughly normalized to `0.0..=1.0` against recent peaks, so
+/// scenes don't need to care about the input gain.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AudioFrame {
+    /// RMS level of the signal.
+    pub level: f32,
+    /// Energy per frequency band, lowest first.
+    pub bands: [f32; BANDS],
+    /// `bands`, smoothed with a fast attack and slow release.
+    pub envelopes: [f32; BANDS],
+    /// An onset happened since the last frame.
+    pub onset: bool,
+    /// A beat happened since the last frame. Beats are onsets in the lowest
+    /// bands, at most one every `MIN_BEAT_INTERVAL`.
+    pub beat: bool,
+}
+
+/// Where PCM samples are read from.
+///
+/// Raw streams are signed 16-bit little-endian at 44.1 kHz, in stereo, the
+/// same as `arecord -f cd` produces.
+#[derive(Clone, Debug)]
+pub enum AudioSource {
+    /// A WAV file, played back in real time and looped.
+    Wav(String),
+    /// Raw PCM from stdin.
+    Stdin,
+    /// Raw PCM from a named pipe.
+    Pipe(String),
+}
+
+impl AudioSource {
+    /// Parses `stdin`, `wav:<path>` or `pipe:<path>`.
+    pub fn parse(s: &str) -> Option<AudioSource> {
+        if s == "stdin" {
+            Some(AudioSource::Stdin)
+        } else if let Some(path) = s.strip_prefix("wav:") {
+            Some(AudioSource::Wav(path.to_string()))
+        } else {
+            s.strip_prefix("pipe:")
+                .map(|path| AudioSource::Pipe(path.to_string()))
+        }
+    }
+}
+
+const RAW_SAMPLE_RATE: u32 = 44100;
+const RAW_CHANNELS: usize = 2;
+
+/// Reads audio on a background thread and keeps the latest analysis.
+pub struct AudioInput {
+    frame: Arc<Mutex<AudioFrame>>,
+}
+
+impl AudioInput {
+    pub fn start(source: AudioSource) -> io::Result<Self> {
+        let frame = Arc::new(Mutex::new(AudioFrame::default()));
+        let shared = frame.clone();
+
+        match source {
+            AudioSource::Wav(path) => {
+                // Open it here so a bad path fails at startup.
+                hound::WavReader::open(&path).map_err(wav_error)?;
+                thread::spawn(move || loop {
+                    if let Err(e) = run_wav(&path, &shared) {
+                        eprintln!("audio: {}", e);
+                        return;
+                    }
+                });
+            }
+            AudioSource::Stdin => {
+                thread::spawn(move || run_raw(io::stdin(), &shared));
+            }
+            AudioSource::Pipe(path) => {
+                thread::spawn(move || loop {
+                    // A pipe hits EOF whenever its writer goes away, so
+                    // reopen it and wait for the next one.
+                    match File::open(&path) {
+                        Ok(file) => run_raw(BufReader::new(file), &shared),
+                        Err(e) => {
+                            eprintln!("audio: {}", e);
+                            return;
+                        }
+                    }
+                });
+            }
+        }
+
+        Ok(AudioInput { frame })
+    }
+
+    /// Returns the latest analysis. Onsets and beats are reported once, to
+    /// the first caller after they happen.
+    pub fn frame(&self) -> AudioFrame {
+        let mut shared = self.frame.lock().unwrap();
+        let frame = *shared;
+
+        shared.onset = false;
+        shared.beat = false;
+
+        frame
+    }
+}
+
+fn wav_error(e: hound::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn run_wav(path: &str, shared: &Mutex<AudioFrame>) -> io::Result<()> {
+    let mut reader = hound::WavReader::open(path).map_err(wav_error)?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+
+    let mut samples: Box<dyn Iterator<Item = f32>> = match spec.sample_format {
+        hound::SampleFormat::Float => Box::new(reader.samples::<f32>().map(|s| s.unwrap_or(0.0))),
+        hound::SampleFormat::Int => {
+            let scale = (1u64 << (spec.bits_per_sample - 1)) as f32;
+            Box::new(
+                reader
+                    .samples::<i32>()
+                    .map(move |s| s.unwrap_or(0) as f32 / scale),
+            )
+        }
+    };
+
+    let mut analyzer = Analyzer::new(spec.sample_rate);
+    let hop_time = time::Duration::from_secs_f32(HOP_SIZE as f32 / spec.sample_rate as f32);
+    let mut next_hop = time::Instant::now();
+    let mut hop = Vec::with_capacity(HOP_SIZE);
+    let mut hops = 0;
+
+    loop {
+        hop.clear();
+        for _ in 0..HOP_SIZE {
+            let mut sum = 0.0;
+            for _ in 0..channels {
+                match samples.next() {
+                    Some(sample) => sum += sample,
+                    // Looping a file too short to analyze would spin.
+                    None if hops == 0 => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("{} is too short to analyze", path),
+                        ))
+                    }
+                    None => return Ok(()),
+                }
+            }
+            hop.push(sum / channels as f32);
+        }
+
+        analyzer.push(&hop, shared);
+        hops += 1;
+
+        // Play back in real time, so the visuals follow the music.
+        next_hop += hop_time;
+        let now = time::Instant::now();
+        if next_hop > now {
+            thread::sleep(next_hop - now);
+        }
+    }
+}
+
+fn run_raw<R: Read>(mut reader: R, shared: &Mutex<AudioFrame>) {
+    let mut analyzer = Analyzer::new(RAW_SAMPLE_RATE);
+    let mut bytes = vec![0u8; HOP_SIZE * RAW_CHANNELS * 2];
+    let mut hop = Vec::with_capacity(HOP_SIZE);
+
+    while reader.read_exact(&mut bytes).is_ok() {
+        hop.clear();
+        for frame in bytes.chunks_exact(RAW_CHANNELS * 2) {
+            let sum: f32 = frame
+                .chunks_exact(2)
+                .map(|s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0)
+                .sum();
+            hop.push(sum / RAW_CHANNELS as f32);
+        }
+
+        analyzer.push(&hop, shared);
+    }
+}
+
+/// Turns a stream of mono samples into [`AudioFrame`]s.
+struct Analyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    samples: VecDeque<f32>,
+    buffer: Vec<Complex<f32>>,
+
+    /// FFT bin range of each band.
+    band_bins: [(usize, usize); BANDS],
+    band_peaks: [f32; BANDS],
+    level_peak: f32,
+
+    last_bands: [f32; BANDS],
+    flux_history: VecDeque<f32>,
+    bass_flux_history: VecDeque<f32>,
+    /// Beats are timed in audio rather than wall clock time, so that
+    /// bursts of buffered input are still analyzed correctly.
+    min_beat_hops: u32,
+    hops_since_beat: u32,
+}
+
+impl Analyzer {
+    fn new(sample_rate: u32) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+
+        // Hann window.
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                let x = i as f32 / (FFT_SIZE - 1) as f32;
+                0.5 - 0.5 * (std::f32::consts::TAU * x).cos()
+            })
+            .collect();
+
+        let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+        let max_bin = FFT_SIZE / 2;
+        let mut band_bins = [(0, 0); BANDS];
+        for (i, bins) in band_bins.iter_mut().enumerate() {
+            let freq = |i: usize| MIN_FREQ * (MAX_FREQ / MIN_FREQ).powf(i as f32 / BANDS as f32);
+            let lo = ((freq(i) / bin_width) as usize).clamp(1, max_bin - 1);
+            let hi = ((freq(i + 1) / bin_width) as usize).clamp(lo + 1, max_bin);
+            *bins = (lo, hi);
+        }
+
+        Analyzer {
+            fft,
+            window,
+            samples: VecDeque::from(vec![0.0; FFT_SIZE]),
+            buffer: vec![Complex::default(); FFT_SIZE],
+            band_bins,
+            band_peaks: [1e-3; BANDS],
+            level_peak: 1e-3,
+            last_bands: [0.0; BANDS],
+            flux_history: VecDeque::with_capacity(FLUX_HISTORY),
+            bass_flux_history: VecDeque::with_capacity(FLUX_HISTORY),
+            min_beat_hops: (MIN_BEAT_INTERVAL.as_secs_f32() * sample_rate as f32 / HOP_SIZE as f32)
+                as u32,
+            hops_since_beat: 0,
+        }
+    }
+
+    fn push(&mut self, hop: &[f32], shared: &Mutex<AudioFrame>) {
+        for sample in hop {
+            self.samples.pop_front();
+            self.samples.push_back(*sample);
+        }
+
+        for (i, (sample, w)) in self.samples.iter().zip(&self.window).enumerate() {
+            self.buffer[i] = Complex::new(sample * w, 0.0);
+        }
+        self.fft.process(&mut self.buffer);
+
+        let rms = (hop.iter().map(|s| s * s).sum::<f32>() / hop.len() as f32).sqrt();
+        self.level_peak = (self.level_peak * 0.999).max(rms).max(1e-3);
+
+        let mut bands = [0.0; BANDS];
+        for (i, band) in bands.iter_mut().enumerate() {
+            let (lo, hi) = self.band_bins[i];
+            let energy =
+                self.buffer[lo..hi].iter().map(|c| c.norm()).sum::<f32>() / (hi - lo) as f32;
+
+            self.band_peaks[i] = (self.band_peaks[i] * 0.999).max(energy).max(1e-3);
+            *band = (energy / self.band_peaks[i]).clamp(0.0, 1.0);
+        }
+
+        // Spectral flux: how much the bands rose since the last hop.
+        let rise = |i: usize| (bands[i] - self.last_bands[i]).max(0.0);
+        let flux: f32 = (0..BANDS).map(rise).sum();
+        let bass_flux: f32 = (0..2).map(rise).sum();
+        self.last_bands = bands;
+
+        let onset = is_peak(&mut self.flux_history, flux);
+        let bass_onset = is_peak(&mut self.bass_flux_history, bass_flux);
+        self.hops_since_beat = self.hops_since_beat.saturating_add(1);
+        let beat = bass_onset && self.hops_since_beat >= self.min_beat_hops;
+        if beat {
+            self.hops_since_beat = 0;
+        }
+
+        let mut frame = shared.lock().unwrap();
+        frame.level = (rms / self.level_peak).clamp(0.0, 1.0);
+        frame.bands = bands;
+        for (envelope, band) in frame.envelopes.iter_mut().zip(&bands) {
+            let rate = if *band > *envelope {
+                ENVELOPE_ATTACK
+            } else {
+                ENVELOPE_RELEASE
+            };
+            *envelope += (band - *envelope) * rate;
+        }
+        // Latched until read, since hops come faster than frames.
+        frame.onset |= onset;
+        frame.beat |= beat;
+    }
+}
+
+/// Records `value` and tells whether it stands out from the recent history.
+fn is_peak(history: &mut VecDeque<f32>, value: f32) -> bool {
+    let n = history.len().max(1) as f32;
+    let mean = history.iter().sum::<f32>() / n;
+    let variance = history.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / n;
+
+    let peak = history.len() == FLUX_HISTORY
+        && value > 0.05
+        && value > mean + ONSET_THRESHOLD * variance.sqrt();
+
+    if history.len() == FLUX_HISTORY {
+        history.pop_front();
+    }
+    history.push_back(value);
+
+    peak
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    /// Runs `samples` through an analyzer, returning the frame after each
+    /// hop, read as a scene would read it.
+    fn analyze(analyzer: &mut Analyzer, samples: &[f32]) -> Vec<AudioFrame> {
+        let shared = Mutex::new(AudioFrame::default());
+
+        samples
+            .chunks_exact(HOP_SIZE)
+            .map(|hop| {
+                analyzer.push(hop, &shared);
+                let mut frame = shared.lock().unwrap();
+                let read = *frame;
+                frame.onset = false;
+                frame.beat = false;
+                read
+            })
+            .collect()
+    }
+
+    fn sine(freq: f32, seconds: f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|i| 0.5 * (std::f32::consts::TAU * freq * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    /// Single-sample clicks every `interval` seconds, starting after one
+    /// interval of silence.
+    fn clicks(interval: f32, seconds: f32) -> Vec<f32> {
+        let every = (interval * SAMPLE_RATE as f32) as usize;
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|i| if i > 0 && i % every == 0 { 1.0 } else { 0.0 })
+            .collect()
+    }
+
+    /// The band a frequency falls in.
+    fn band_of(freq: f32) -> usize {
+        (((freq / MIN_FREQ).ln() / (MAX_FREQ / MIN_FREQ).ln()) * BANDS as f32) as usize
+    }
+
+    fn loudest(bands: &[f32; BANDS]) -> usize {
+        (0..BANDS)
+            .max_by(|&a, &b| bands[a].total_cmp(&bands[b]))
+            .unwrap()
+    }
+
+    #[test]
+    fn sines_raise_their_band() {
+        let mut analyzer = Analyzer::new(SAMPLE_RATE);
+        let (low, high) = (band_of(120.0), band_of(1000.0));
+        assert_ne!(low, high);
+
+        let frames = analyze(&mut analyzer, &sine(120.0, 1.0));
+        let last = frames.last().unwrap();
+        assert_eq!(loudest(&last.bands), low, "{:?}", last.bands);
+        assert!(last.bands[low] > 0.5, "{:?}", last.bands);
+        assert!(last.envelopes[low] > 0.5, "{:?}", last.envelopes);
+        assert!(last.level > 0.9);
+
+        let frames = analyze(&mut analyzer, &sine(1000.0, 1.0));
+        let last = frames.last().unwrap();
+        assert_eq!(loudest(&last.bands), high, "{:?}", last.bands);
+        assert!(last.bands[high] > 0.5, "{:?}", last.bands);
+        assert!(last.bands[low] < 0.1, "{:?}", last.bands);
+    }
+
+    #[test]
+    fn silence_has_no_onsets() {
+        let mut analyzer = Analyzer::new(SAMPLE_RATE);
+        let frames = analyze(&mut analyzer, &vec![0.0; SAMPLE_RATE as usize * 2]);
+
+        assert!(frames.iter().all(|frame| !frame.onset && !frame.beat));
+        assert!(frames.iter().all(|frame| frame.level == 0.0));
+    }
+
+    #[test]
+    fn clicks_are_onsets_and_beats() {
+        let interval = 0.5;
+        let mut analyzer = Analyzer::new(SAMPLE_RATE);
+        let frames = analyze(&mut analyzer, &clicks(interval, 6.0));
+
+        // The hops each click lands in, after the first second has filled
+        // the flux history.
+        let every = (interval * SAMPLE_RATE as f32) as usize;
+        let first = SAMPLE_RATE as usize / HOP_SIZE;
+        let click_hops: Vec<usize> = (1..)
+            .map(|k| k * every / HOP_SIZE)
+            .take_while(|&hop| hop < frames.len())
+            .filter(|&hop| hop >= first)
+            .collect();
+        let near_click = |hop: usize| click_hops.iter().any(|&c| hop == c || hop == c + 1);
+
+        for &click in &click_hops {
+            assert!(frames[click].onset || frames[click + 1].onset);
+            assert!(frames[click].beat || frames[click + 1].beat);
+        }
+        for (hop, frame) in frames.iter().enumerate().skip(first) {
+            assert!(!frame.onset || near_click(hop), "onset at hop {}", hop);
+        }
+
+        let beats = frames[first..].iter().filter(|frame| frame.beat).count();
+        assert_eq!(beats, click_hops.len());
+    }
+
+    #[test]
+    fn beats_are_spaced_out() {
+        let mut analyzer = Analyzer::new(SAMPLE_RATE);
+        let frames = analyze(&mut analyzer, &clicks(0.1, 6.0));
+
+        let beats: Vec<usize> = frames
+            .iter()
+            .enumerate()
+            .filter(|(_, frame)| frame.beat)
+            .map(|(i, _)| i)
+            .collect();
+        assert!(!beats.is_empty());
+        for pair in beats.windows(2) {
+            assert!(pair[1] - pair[0] >= analyzer.min_beat_hops as usize);
+        }
+    }
+
+    #[test]
+    fn empty_wavs_are_refused() {
+        let path = std::env::temp_dir().join(format!("matryx_empty_{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        hound::WavWriter::create(&path, spec)
+            .unwrap()
+            .finalize()
+            .unwrap();
+
+        let result = run_wav(path.to_str().unwrap(), &Mutex::new(AudioFrame::default()));
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
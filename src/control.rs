@@ -0,0 +1,101 @@
+use crate::output::Output;
+use crate::scenes::SceneList;
+
+/// A request to change the running generator, from any control input.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Control {
+    NextScene,
+    PrevScene,
+    SelectScene(String),
+    /// Sets a parameter of the scene called `scene`, or of the current
+    /// scene if `scene` is `None`. `value` is normalized to `0.0..=1.0` and
+    /// mapped onto the parameter's range.
+    SetParam {
+        scene: Option<String>,
+        name: String,
+        value: f32,
+    },
+    Brightness(f32),
+    /// Gravity for scenes that simulate it, as read from an accelerometer:
+    /// in g, with x going right and y going down the canvas. Scenes take it
+    /// through their `gravity_x` and `gravity_y` parameters.
+    Gravity {
+        x: f32,
+        y: f32,
+    },
+    /// Pokes the current scene.
+    Disturb(Disturbance),
+    /// Sent to the current scene's `on_event`.
+    Event(Event),
+}
+
+/// Energy added to a scene from outside, at positions given as fractions of
+/// the canvas, `0.0..=1.0` with y going down.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Disturbance {
+    /// A drop at a point.
+    Point { x: f32, y: f32, strength: f32 },
+    /// A stroke from one point to another.
+    Line {
+        from: (f32, f32),
+        to: (f32, f32),
+        strength: f32,
+    },
+}
+
+/// Something that happened, for the current scene to react to as it sees
+/// fit. Scenes ignore events they don't know.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub name: String,
+    /// A value sent along with the event, if any.
+    pub value: Option<f32>,
+}
+
+impl Control {
+    pub fn apply(self, scenes: &mut SceneList, output: &mut Output) {
+        match self {
+            Control::NextScene => scenes.next(),
+            Control::PrevScene => scenes.prev(),
+            Control::SelectScene(name) => {
+                if !scenes.select(&name) {
+                    eprintln!("control: no scene called {:?}", name);
+                }
+            }
+            Control::SetParam { scene, name, value } => {
+                let scene = match &scene {
+                    Some(scene_name) => match scenes.get(scene_name) {
+                        Some(scene) => scene,
+                        None => {
+                            eprintln!("control: no scene called {:?}", scene_name);
+                            return;
+                        }
+                    },
+                    None => scenes.current(),
+                };
+
+                match scene.params().iter().find(|param| param.name == name) {
+                    Some(param) => scene.set_param(&name, param.denormalize(value)),
+                    None => eprintln!(
+                        "control: scene {:?} has no parameter {:?}",
+                        scene.name(),
+                        name
+                    ),
+                }
+            }
+            Control::Brightness(brightness) => output.set_brightness(brightness),
+            Control::Gravity { x, y } => {
+                for scene in scenes.iter_mut() {
+                    for (name, value) in [("gravity_x", x), ("gravity_y", y)] {
+                        let params = scene.params();
+                        if let Some(param) = params.iter().find(|param| param.name == name) {
+                            scene.set_param(name, value.clamp(param.min, param.max));
+                        }
+                    }
+                }
+            }
+            Control::Disturb(disturbance) => scenes.current().disturb(&disturbance),
+            Control::Event(event) => scenes.current().on_event(&event),
+        }
+    }
+}
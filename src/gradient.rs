@@ -0,0 +1,363 @@
+use palette::{FromColor, IntoColor, LinSrgb, Oklab, Srgb};
+use std::f32::consts::TAU;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const LUT_SIZE: usize = 256;
+
+/// Multi-stop gradient, interpolated in Oklab.
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    stops: Vec<(f32, Oklab)>,
+}
+
+impl Gradient {
+    /// Builds a gradient from `(position, color)` stops. Positions are
+    /// sorted, and should lie in `0.0..=1.0`.
+    pub fn new(stops: &[(f32, Srgb)]) -> Self {
+        let mut stops: Vec<(f32, Oklab)> = stops
+            .iter()
+            .map(|(t, color)| (*t, Oklab::from_color(*color)))
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Gradient { stops }
+    }
+
+    /// Evenly spaced stops from hex colors like `"#ff8800"`.
+    fn even(hex: &[&str]) -> Self {
+        let n = (hex.len() - 1).max(1) as f32;
+        let stops: Vec<(f32, Srgb)> = hex
+            .iter()
+            .enumerate()
+            .map(|(i, h)| (i as f32 / n, parse_hex(h).unwrap()))
+            .collect();
+
+        Gradient::new(&stops)
+    }
+
+    pub fn sample(&self, t: f32) -> Srgb {
+        let stops = &self.stops;
+        let first = stops[0];
+        let last = stops[stops.len() - 1];
+
+        let color = if t <= first.0 {
+            first.1
+        } else if t >= last.0 {
+            last.1
+        } else {
+            let i = stops.iter().position(|stop| stop.0 > t).unwrap();
+            let (t0, c0) = stops[i - 1];
+            let (t1, c1) = stops[i];
+            let f = (t - t0) / (t1 - t0);
+
+            Oklab::new(
+                c0.l + (c1.l - c0.l) * f,
+                c0.a + (c1.a - c0.a) * f,
+                c0.b + (c1.b - c0.b) * f,
+            )
+        };
+
+        let rgb: LinSrgb = color.into_color();
+        let rgb = LinSrgb::new(
+            rgb.red.clamp(0.0, 1.0),
+            rgb.green.clamp(0.0, 1.0),
+            rgb.blue.clamp(0.0, 1.0),
+        );
+
+        Srgb::from_linear(rgb)
+    }
+}
+
+/// Cosine palette, `a + b * cos(2π(c * t + d))` per channel, as described by
+/// Inigo Quilez.
+#[derive(Copy, Clone, Debug)]
+pub struct CosinePalette {
+    pub a: [f32; 3],
+    pub b: [f32; 3],
+    pub c: [f32; 3],
+    pub d: [f32; 3],
+}
+
+impl CosinePalette {
+    pub fn sample(&self, t: f32) -> Srgb {
+        let channel = |i: usize| {
+            (self.a[i] + self.b[i] * (TAU * (self.c[i] * t + self.d[i])).cos()).clamp(0.0, 1.0)
+        };
+
+        Srgb::new(channel(0), channel(1), channel(2))
+    }
+}
+
+/// Maps a scalar in `0.0..=1.0` to a color.
+///
+/// Palettes are baked into a lookup table when created, so sampling per
+/// pixel is cheap.
+#[derive(Clone)]
+pub struct Palette {
+    lut: Vec<[f32; 3]>,
+}
+
+impl Palette {
+    fn from_fn<F: Fn(f32) -> Srgb>(f: F) -> Self {
+        let lut = (0..LUT_SIZE)
+            .map(|i| {
+                let color = f(i as f32 / (LUT_SIZE - 1) as f32);
+                [color.red, color.green, color.blue]
+            })
+            .collect();
+
+        Palette { lut }
+    }
+
+    pub fn from_gradient(gradient: &Gradient) -> Self {
+        Palette::from_fn(|t| gradient.sample(t))
+    }
+
+    pub fn from_cosine(cosine: &CosinePalette) -> Self {
+        Palette::from_fn(|t| cosine.sample(t))
+    }
+
+    /// Looks up one of the built-in palettes. See [`Palette::NAMES`].
+    pub fn named(name: &str) -> Option<Palette> {
+        let palette = match name {
+            "grayscale" => Palette::from_gradient(&Gradient::even(&["#000000", "#ffffff"])),
+            "fire" => Palette::from_gradient(&Gradient::even(&[
+                "#000000", "#5c0a00", "#d42a00", "#ff9a1f", "#fff3b0",
+            ])),
+            "ocean" => Palette::from_gradient(&Gradient::even(&[
+                "#00040f", "#002b5c", "#00807a", "#5fd3b8", "#e8fff8",
+            ])),
+            "sunset" => Palette::from_gradient(&Gradient::even(&[
+                "#10002b", "#5a189a", "#e0457b", "#ff9e00", "#ffe8a3",
+            ])),
+            "ice" => Palette::from_gradient(&Gradient::even(&[
+                "#000000", "#0d1b4c", "#3a7bd5", "#b8e6ff", "#ffffff",
+            ])),
+            "rainbow" => Palette::from_cosine(&CosinePalette {
+                a: [0.5, 0.5, 0.5],
+                b: [0.5, 0.5, 0.5],
+                c: [1.0, 1.0, 1.0],
+                d: [0.0, 0.33, 0.67],
+            }),
+            "candy" => Palette::from_cosine(&CosinePalette {
+                a: [0.5, 0.5, 0.5],
+                b: [0.5, 0.5, 0.5],
+                c: [1.0, 1.0, 0.5],
+                d: [0.8, 0.9, 0.3],
+            }),
+            "acid" => Palette::from_cosine(&CosinePalette {
+                a: [0.5, 0.5, 0.5],
+                b: [0.5, 0.5, 0.5],
+                c: [2.0, 1.0, 0.0],
+                d: [0.5, 0.2, 0.25],
+            }),
+            _ => return None,
+        };
+
+        Some(palette)
+    }
+
+    pub const NAMES: &'static [&'static str] = &[
+        "grayscale",
+        "fire",
+        "ocean",
+        "sunset",
+        "ice",
+        "rainbow",
+        "candy",
+        "acid",
+    ];
+
+    /// A built-in palette name, or else the path of a palette file.
+    pub fn named_or_load(name: &str) -> io::Result<Palette> {
+        match Palette::named(name) {
+            Some(palette) => Ok(palette),
+            None => Palette::load(name),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Palette> {
+        Palette::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses a palette file. It either lists gradient stops, one
+    /// `position color` pair per line, or holds a single `cosine` line with
+    /// the `a`, `b`, `c` and `d` vectors. Colors are written as `#rrggbb`;
+    /// any other word starting with `#` begins a comment.
+    ///
+    /// ```text
+    /// 0.0  #000000
+    /// 0.3  #d42a00
+    /// 1.0  #fff3b0
+    /// ```
+    ///
+    /// ```text
+    /// cosine  0.5 0.5 0.5  0.5 0.5 0.5  1.0 1.0 1.0  0.0 0.33 0.67
+    /// ```
+    pub fn parse(text: &str) -> io::Result<Palette> {
+        let mut stops = Vec::new();
+
+        for (n, line) in text.lines().enumerate() {
+            let words: Vec<&str> = line
+                .split_whitespace()
+                .take_while(|w| !w.starts_with('#') || parse_hex(w).is_some())
+                .collect();
+            let bad_line = || invalid(format!("line {}: can't parse {:?}", n + 1, line.trim()));
+
+            match words.as_slice() {
+                [] => {}
+                ["cosine", rest @ ..] if rest.len() == 12 => {
+                    let mut v = [0.0; 12];
+                    for (i, word) in rest.iter().enumerate() {
+                        v[i] = number(word).ok_or_else(bad_line)?;
+                    }
+
+                    return Ok(Palette::from_cosine(&CosinePalette {
+                        a: [v[0], v[1], v[2]],
+                        b: [v[3], v[4], v[5]],
+                        c: [v[6], v[7], v[8]],
+                        d: [v[9], v[10], v[11]],
+                    }));
+                }
+                [t, color] => {
+                    let t = number(t).ok_or_else(bad_line)?;
+                    let color = parse_hex(color).ok_or_else(bad_line)?;
+                    stops.push((t, color));
+                }
+                _ => return Err(bad_line()),
+            }
+        }
+
+        if stops.is_empty() {
+            return Err(invalid("palette has no colors".to_string()));
+        }
+
+        Ok(Palette::from_gradient(&Gradient::new(&stops)))
+    }
+
+    /// Samples the palette at `t`, clamped to `0.0..=1.0`.
+    pub fn sample(&self, t: f32) -> (f32, f32, f32) {
+        let i = (t.clamp(0.0, 1.0) * (LUT_SIZE - 1) as f32) as usize;
+        let [r, g, b] = self.lut[i];
+        (r, g, b)
+    }
+}
+
+/// A finite number, since `nan` and `inf` parse as floats too.
+fn number(s: &str) -> Option<f32> {
+    s.parse().ok().filter(|v: &f32| v.is_finite())
+}
+
+fn parse_hex(s: &str) -> Option<Srgb> {
+    let s = s.strip_prefix('#')?;
+    if s.len() != 6 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&s[i..i + 2], 16).ok();
+    let color = Srgb::new(channel(0)?, channel(2)?, channel(4)?);
+
+    Some(color.into_format())
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: (f32, f32, f32), b: (f32, f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 0.01 && (a.1 - b.1).abs() < 0.01 && (a.2 - b.2).abs() < 0.01
+    }
+
+    fn error(text: &str) -> String {
+        Palette::parse(text).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn gradients_hit_their_stops() {
+        let red = parse_hex("#ff0000").unwrap();
+        let gradient = Gradient::new(&[
+            (1.0, parse_hex("#ffffff").unwrap()),
+            (0.0, parse_hex("#000000").unwrap()),
+            (0.25, red),
+        ]);
+        let rgb = |t: f32| {
+            let color = gradient.sample(t);
+            (color.red, color.green, color.blue)
+        };
+
+        assert!(close(rgb(0.0), (0.0, 0.0, 0.0)));
+        assert!(close(rgb(0.25), (1.0, 0.0, 0.0)));
+        assert!(close(rgb(1.0), (1.0, 1.0, 1.0)));
+        // Past the ends, the end stops hold.
+        assert!(close(rgb(-1.0), (0.0, 0.0, 0.0)));
+        assert!(close(rgb(2.0), (1.0, 1.0, 1.0)));
+        // In between, it's neither neighbor.
+        let mid = rgb(0.6);
+        assert!(!close(mid, (1.0, 0.0, 0.0)) && !close(mid, (1.0, 1.0, 1.0)));
+        assert!(mid.0 > 0.9 && mid.1 > 0.1 && mid.1 < 0.9);
+    }
+
+    #[test]
+    fn parses_palette_files() {
+        let palette = Palette::parse(
+            "# fire\n\
+             0.0  #000000\n\
+             \n\
+             0.5  #FF8800  # orange\n\
+             1.0  #ffffff\n",
+        )
+        .unwrap();
+
+        assert!(close(palette.sample(0.0), (0.0, 0.0, 0.0)));
+        assert!(close(palette.sample(0.5), (1.0, 0x88 as f32 / 255.0, 0.0)));
+        assert!(close(palette.sample(1.0), (1.0, 1.0, 1.0)));
+        assert!(close(palette.sample(7.0), (1.0, 1.0, 1.0)));
+
+        let cosine = Palette::parse("cosine 0.5 0.5 0.5  0.5 0.5 0.5  1 1 1  0 0 0.5\n").unwrap();
+        assert!(close(cosine.sample(0.0), (1.0, 1.0, 0.0)));
+        assert!(close(cosine.sample(1.0), (1.0, 1.0, 0.0)));
+
+        for name in Palette::NAMES {
+            assert!(Palette::named(name).is_some());
+        }
+        assert!(Palette::named("plaid").is_none());
+    }
+
+    #[test]
+    fn rejects_bad_palettes() {
+        assert_eq!(error(""), "palette has no colors");
+        assert_eq!(error("0.5\n"), "line 1: can't parse \"0.5\"");
+        assert_eq!(
+            error("0.0 #000000\nhalf #ffffff\n"),
+            "line 2: can't parse \"half #ffffff\""
+        );
+        assert_eq!(
+            error("nan #ffffff\n"),
+            "line 1: can't parse \"nan #ffffff\""
+        );
+        assert_eq!(
+            error("inf #ffffff\n"),
+            "line 1: can't parse \"inf #ffffff\""
+        );
+        assert_eq!(error("0.0 #fffff\n"), "line 1: can't parse \"0.0 #fffff\"");
+        assert_eq!(
+            error("0.0 #ff\u{e9}ff\n"),
+            "line 1: can't parse \"0.0 #ff\u{e9}ff\""
+        );
+        assert_eq!(
+            error("0.0 #gg0000\n"),
+            "line 1: can't parse \"0.0 #gg0000\""
+        );
+        assert_eq!(
+            error("cosine 1 2 3\n"),
+            "line 1: can't parse \"cosine 1 2 3\""
+        );
+        assert!(Palette::parse("cosine 0 0 0 0 0 0 0 0 0 0 0 nan\n").is_err());
+    }
+}
@@ -0,0 +1,405 @@
+use std::ops::{Index, IndexMut};
+use std::slice;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// How reads outside a grid are resolved.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Boundary<T> {
+    /// The nearest edge cell.
+    Clamp,
+    /// The opposite edge, as on a torus.
+    Wrap,
+    /// Reflected back in at the edge, without repeating the edge cell.
+    Mirror,
+    /// A fixed value.
+    Constant(T),
+}
+
+impl<T> Boundary<T> {
+    /// Maps `v` onto `0..size`, or `None` if it reads the constant or there
+    /// is nothing to read.
+    fn resolve(&self, v: i32, size: usize) -> Option<usize> {
+        let size = size as i32;
+        if (0..size).contains(&v) {
+            return Some(v as usize);
+        }
+
+        match self {
+            _ if size == 0 => None,
+            Boundary::Clamp => Some(v.clamp(0, size - 1) as usize),
+            Boundary::Wrap => Some(v.rem_euclid(size) as usize),
+            Boundary::Mirror if size == 1 => Some(0),
+            Boundary::Mirror => {
+                let period = 2 * (size - 1);
+                let v = v.rem_euclid(period);
+                Some(if v < size { v } else { period - v } as usize)
+            }
+            Boundary::Constant(_) => None,
+        }
+    }
+}
+
+/// Which cells around a center count as its neighbors.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Neighborhood {
+    /// Cells up to `radius` steps away along the axes combined, the four
+    /// closest for a radius of 1.
+    VonNeumann(u32),
+    /// Cells in the square `radius` around, the eight closest for a radius
+    /// of 1.
+    Moore(u32),
+}
+
+impl Neighborhood {
+    /// Offsets of the neighbors row by row, leaving out the center.
+    pub fn offsets(self) -> impl Iterator<Item = (i32, i32)> {
+        let (radius, diamond) = match self {
+            Neighborhood::VonNeumann(radius) => (radius as i32, true),
+            Neighborhood::Moore(radius) => (radius as i32, false),
+        };
+
+        (-radius..=radius)
+            .flat_map(move |dy| (-radius..=radius).map(move |dx| (dx, dy)))
+            .filter(move |&(dx, dy)| {
+                (dx, dy) != (0, 0) && (!diamond || dx.abs() + dy.abs() <= radius)
+            })
+    }
+}
+
+/// A 2D grid of cells stored row by row, with a [`Boundary`] deciding what
+/// reads past its edges see.
+#[derive(Clone, Debug)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    cells: Vec<T>,
+    boundary: Boundary<T>,
+}
+
+impl<T: Copy> Grid<T> {
+    /// A grid filled with `value`, clamping reads at its edges.
+    pub fn new(width: usize, height: usize, value: T) -> Self {
+        Grid {
+            width,
+            height,
+            cells: vec![value; width * height],
+            boundary: Boundary::Clamp,
+        }
+    }
+
+    /// A grid with each cell set from `f(x, y)`.
+    pub fn from_fn<F: FnMut(usize, usize) -> T>(width: usize, height: usize, mut f: F) -> Self {
+        let cells = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| f(x, y))
+            .collect();
+
+        Grid {
+            width,
+            height,
+            cells,
+            boundary: Boundary::Clamp,
+        }
+    }
+
+    /// A `width` by `height` copy, stretched or squeezed so each cell takes
+    /// the value of the nearest one here. The grid must not be empty.
+    pub fn resized(&self, width: usize, height: usize) -> Self {
+        Grid::from_fn(width, height, |x, y| {
+            self[(x * self.width / width, y * self.height / height)]
+        })
+        .with_boundary(self.boundary)
+    }
+
+    pub fn with_boundary(mut self, boundary: Boundary<T>) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Whether the grid has no cells, being 0 wide or 0 tall.
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height
+    }
+
+    /// The cell at (`x`, `y`), if it's inside the grid.
+    pub fn get(&self, x: i32, y: i32) -> Option<T> {
+        if self.in_bounds(x, y) {
+            Some(self.cells[y as usize * self.width + x as usize])
+        } else {
+            None
+        }
+    }
+
+    /// The cell at (`x`, `y`), with the grid's boundary deciding what's
+    /// read outside it.
+    pub fn sample(&self, x: i32, y: i32) -> T {
+        let x = self.boundary.resolve(x, self.width);
+        let y = self.boundary.resolve(y, self.height);
+
+        match (x, y, &self.boundary) {
+            (Some(x), Some(y), _) => self.cells[y * self.width + x],
+            (_, _, Boundary::Constant(value)) => *value,
+            _ => panic!("can't sample an empty grid"),
+        }
+    }
+
+    /// Neighbors of (`x`, `y`) as offsets and values, sampled through the
+    /// boundary.
+    pub fn neighbors(
+        &self,
+        x: i32,
+        y: i32,
+        neighborhood: Neighborhood,
+    ) -> impl Iterator<Item = ((i32, i32), T)> + '_ {
+        neighborhood
+            .offsets()
+            .map(move |(dx, dy)| ((dx, dy), self.sample(x + dx, y + dy)))
+    }
+
+    /// Mirrors the grid top to bottom.
+    pub fn flip_vertical(&mut self) {
+        let width = self.width;
+        for y in 0..self.height / 2 {
+            let (top, bottom) = self.cells.split_at_mut((self.height - 1 - y) * width);
+            top[y * width..(y + 1) * width].swap_with_slice(&mut bottom[..width]);
+        }
+    }
+
+    /// All cells, row by row.
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.cells.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, T> {
+        self.cells.iter_mut()
+    }
+
+    pub fn rows(&self) -> slice::Chunks<'_, T> {
+        self.cells.chunks(self.width.max(1))
+    }
+
+    pub fn rows_mut(&mut self) -> slice::ChunksMut<'_, T> {
+        self.cells.chunks_mut(self.width.max(1))
+    }
+
+    /// Calls `f` with each row's index and cells, on several threads when
+    /// built with the `parallel` feature.
+    pub fn par_rows_mut<F>(&mut self, f: F)
+    where
+        T: Send,
+        F: Fn(usize, &mut [T]) + Sync + Send,
+    {
+        par_chunks_mut(&mut self.cells, self.width, f);
+    }
+}
+
+/// Calls `f` with the index and contents of each `size` long chunk of
+/// `items`, on several threads when built with the `parallel` feature.
+pub fn par_chunks_mut<T, F>(items: &mut [T], size: usize, f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync + Send,
+{
+    let size = size.max(1);
+
+    #[cfg(feature = "parallel")]
+    items
+        .par_chunks_mut(size)
+        .enumerate()
+        .for_each(|(i, chunk)| f(i, chunk));
+
+    #[cfg(not(feature = "parallel"))]
+    items
+        .chunks_mut(size)
+        .enumerate()
+        .for_each(|(i, chunk)| f(i, chunk));
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        assert!(
+            x < self.width,
+            "x {} out of bounds for width {}",
+            x,
+            self.width
+        );
+        &self.cells[y * self.width + x]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+        assert!(
+            x < self.width,
+            "x {} out of bounds for width {}",
+            x,
+            self.width
+        );
+        &mut self.cells[y * self.width + x]
+    }
+}
+
+/// Two grids of the same size: the front one is read while the back one is
+/// written, then they're swapped.
+#[derive(Clone, Debug)]
+pub struct DoubleBuffer<T> {
+    front: Grid<T>,
+    back: Grid<T>,
+}
+
+impl<T: Copy> DoubleBuffer<T> {
+    pub fn new(grid: Grid<T>) -> Self {
+        DoubleBuffer {
+            back: grid.clone(),
+            front: grid,
+        }
+    }
+
+    pub fn front(&self) -> &Grid<T> {
+        &self.front
+    }
+
+    pub fn front_mut(&mut self) -> &mut Grid<T> {
+        &mut self.front
+    }
+
+    /// The front grid to read from and the back grid to write to.
+    pub fn split(&mut self) -> (&Grid<T>, &mut Grid<T>) {
+        (&self.front, &mut self.back)
+    }
+
+    /// Makes the back grid the front one.
+    pub fn swap(&mut self) {
+        std::mem::swap(&mut self.front, &mut self.back);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x2 grid numbered row by row.
+    fn numbered(boundary: Boundary<i32>) -> Grid<i32> {
+        Grid::from_fn(3, 2, |x, y| (y * 3 + x) as i32).with_boundary(boundary)
+    }
+
+    fn row(grid: &Grid<i32>, y: i32) -> Vec<i32> {
+        (-4..7).map(|x| grid.sample(x, y)).collect()
+    }
+
+    #[test]
+    fn clamp_repeats_the_edge() {
+        let grid = numbered(Boundary::Clamp);
+        assert_eq!(row(&grid, 0), [0, 0, 0, 0, 0, 1, 2, 2, 2, 2, 2]);
+        assert_eq!(grid.sample(1, -5), 1);
+        assert_eq!(grid.sample(1, 5), 4);
+    }
+
+    #[test]
+    fn wrap_tiles_the_grid() {
+        let grid = numbered(Boundary::Wrap);
+        assert_eq!(row(&grid, 0), [2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0]);
+        assert_eq!(grid.sample(0, -1), 3);
+        assert_eq!(grid.sample(0, 2), 0);
+    }
+
+    #[test]
+    fn mirror_reflects_without_repeating_the_edge() {
+        let grid = numbered(Boundary::Mirror);
+        assert_eq!(row(&grid, 0), [0, 1, 2, 1, 0, 1, 2, 1, 0, 1, 2]);
+        assert_eq!(grid.sample(2, -1), 5);
+        assert_eq!(grid.sample(2, 2), 2);
+
+        let single = Grid::new(1, 1, 7).with_boundary(Boundary::Mirror);
+        assert_eq!(single.sample(-3, 4), 7);
+    }
+
+    #[test]
+    fn resized_takes_the_nearest_cell() {
+        let grid = numbered(Boundary::Wrap);
+
+        let wide = grid.resized(6, 2);
+        assert_eq!(wide.rows().next().unwrap(), [0, 0, 1, 1, 2, 2]);
+        assert_eq!(wide.sample(-1, 0), 2);
+
+        let small = grid.resized(2, 1);
+        assert_eq!(small.iter().copied().collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[test]
+    fn constant_is_read_outside() {
+        let grid = numbered(Boundary::Constant(-1));
+        assert_eq!(row(&grid, 1), [-1, -1, -1, -1, 3, 4, 5, -1, -1, -1, -1]);
+        assert_eq!(grid.sample(1, -1), -1);
+    }
+
+    #[test]
+    fn get_stays_inside() {
+        let grid = numbered(Boundary::Wrap);
+        assert_eq!(grid.get(2, 1), Some(5));
+        assert_eq!(grid.get(3, 0), None);
+        assert_eq!(grid.get(0, -1), None);
+    }
+
+    #[test]
+    fn neighborhoods() {
+        let von_neumann: Vec<_> = Neighborhood::VonNeumann(1).offsets().collect();
+        assert_eq!(von_neumann, [(0, -1), (-1, 0), (1, 0), (0, 1)]);
+        assert_eq!(Neighborhood::VonNeumann(2).offsets().count(), 12);
+        assert_eq!(Neighborhood::Moore(1).offsets().count(), 8);
+        assert_eq!(Neighborhood::Moore(2).offsets().count(), 24);
+
+        let grid = numbered(Boundary::Constant(-1));
+        let values: Vec<_> = grid
+            .neighbors(0, 0, Neighborhood::VonNeumann(1))
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(values, [-1, -1, 1, 3]);
+    }
+
+    #[test]
+    fn flip_vertical_reverses_rows() {
+        let mut grid = Grid::from_fn(2, 3, |x, y| (y * 2 + x) as i32);
+        grid.flip_vertical();
+        let rows: Vec<&[i32]> = grid.rows().collect();
+        assert_eq!(rows, [&[4, 5][..], &[2, 3], &[0, 1]]);
+    }
+
+    #[test]
+    fn par_rows_mut_visits_every_row() {
+        let mut grid = Grid::new(4, 5, 0);
+        grid.par_rows_mut(|y, row| {
+            for (x, cell) in row.iter_mut().enumerate() {
+                *cell = (y * 10 + x) as i32;
+            }
+        });
+        assert_eq!(grid[(3, 4)], 43);
+        assert_eq!(grid.iter().sum::<i32>(), (0..5).map(|y| y * 40 + 6).sum());
+    }
+
+    #[test]
+    fn double_buffer_swaps() {
+        let mut buffer = DoubleBuffer::new(Grid::new(2, 2, 0));
+        let (front, back) = buffer.split();
+        back[(1, 1)] = front[(1, 1)] + 1;
+        buffer.swap();
+        assert_eq!(buffer.front()[(1, 1)], 1);
+        assert_eq!(buffer.split().1[(1, 1)], 0);
+    }
+}
@@ -0,0 +1,235 @@
+//! The engine behind `matryx_generator`: a [`Canvas`] that [`Scene`]s draw
+//! on, a [`FrameTimer`] pacing them, and [`output::Output`] sending frames
+//! to the matrix. Scenes can live in other crates and be bundled into custom
+//! binaries alongside the built-in ones in [`scenes`].
+
+pub mod audio;
+pub mod control;
+pub mod gradient;
+pub mod grid;
+pub mod mapping;
+pub mod midi;
+pub mod osc;
+pub mod output;
+pub mod params;
+pub mod scenes;
+pub mod watch;
+
+use std::time;
+
+use audio::AudioFrame;
+use control::{Disturbance, Event};
+use params::Param;
+
+/// Time between frames, for 30 frames per second.
+pub const FRAME_TIME: time::Duration = time::Duration::from_millis((1000 / 30) as u64);
+
+/// Paces the main loop and produces a [`FrameTick`] per frame.
+pub struct FrameTimer {
+    prev_tick: Option<FrameTick>,
+}
+
+/// What a scene gets to know about the frame it's drawing.
+#[derive(Copy, Clone, Debug)]
+pub struct FrameTick {
+    /// When the first frame started.
+    pub start: time::Instant,
+    /// When this frame started.
+    pub instant: time::Instant,
+    /// Time since the last frame.
+    pub delta: time::Duration,
+
+    /// Seconds since the first frame.
+    pub t: f32,
+    /// Seconds since the last frame.
+    pub dt: f32,
+
+    pub audio: AudioFrame,
+}
+
+impl FrameTick {
+    pub fn from_start() -> FrameTick {
+        let now = time::Instant::now();
+
+        FrameTick {
+            start: now,
+            instant: now,
+            delta: time::Duration::from_millis(0),
+            t: 0.0,
+            dt: 0.0,
+            audio: AudioFrame::default(),
+        }
+    }
+
+    pub fn from_prev(last_tick: &FrameTick) -> FrameTick {
+        let start = last_tick.start;
+        let instant = time::Instant::now();
+        let delta = last_tick.instant.elapsed();
+        let t = start.elapsed().as_secs_f32();
+        let dt = delta.as_secs_f32();
+
+        FrameTick {
+            start,
+            instant,
+            delta,
+            t,
+            dt,
+            audio: AudioFrame::default(),
+        }
+    }
+}
+
+impl Default for FrameTimer {
+    fn default() -> Self {
+        FrameTimer::new()
+    }
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        FrameTimer { prev_tick: None }
+    }
+
+    pub fn tick(&mut self) -> FrameTick {
+        if self.prev_tick.is_none() {
+            self.prev_tick = Some(FrameTick::from_start());
+            return self.prev_tick.unwrap();
+        } else {
+            self.prev_tick = Some(FrameTick::from_prev(self.prev_tick.as_ref().unwrap()))
+        }
+
+        self.prev_tick.unwrap()
+    }
+
+    /// Sleeps until it's time for the next frame.
+    pub fn wait_for_next_frame(&self) {
+        if self.prev_tick.is_none() {
+            return;
+        }
+
+        let delta = self.prev_tick.unwrap().instant.elapsed();
+        if delta < FRAME_TIME {
+            std::thread::sleep(FRAME_TIME - delta);
+        }
+    }
+}
+
+/// Something that draws on the canvas, one frame at a time.
+pub trait Scene {
+    /// Short name used to address the scene from control inputs.
+    fn name(&self) -> &str;
+
+    /// The scene's parameters, with their current values.
+    fn params(&self) -> Vec<Param> {
+        Vec::new()
+    }
+
+    /// Sets a parameter to `value`, already within the parameter's range.
+    fn set_param(&mut self, _name: &str, _value: f32) {}
+
+    fn tick(&mut self, _canvas: &mut Canvas, _tick: &FrameTick) {}
+
+    /// Adds energy from outside, for scenes that react to it.
+    fn disturb(&mut self, _disturbance: &Disturbance) {}
+
+    /// Called when the scene starts showing.
+    fn on_enter(&mut self) {}
+
+    /// Called when another scene takes over.
+    fn on_exit(&mut self) {}
+
+    /// Called when the canvas changes size, before the next `tick` draws on
+    /// it. Scenes keeping anything sized to the canvas rebuild it here.
+    fn on_resize(&mut self, _width: u32, _height: u32) {}
+
+    /// Reacts to an event sent to the current scene.
+    fn on_event(&mut self, _event: &Event) {}
+}
+
+/// The image scenes draw on: RGB bytes, row by row from the top left.
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Self {
+        Canvas {
+            width,
+            height,
+            pixels: vec![0; (width * height * 3) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Changes the size, leaving the canvas black.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.pixels.clear();
+        self.pixels.resize((width * height * 3) as usize, 0);
+    }
+
+    pub fn clear(&mut self) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel = 0;
+        }
+    }
+
+    pub fn clear_with_color(&mut self, r: f32, g: f32, b: f32) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = (y * self.width + x) * 3;
+                self.pixels[index as usize] = (r * 255.0) as u8;
+                self.pixels[index as usize + 1] = (g * 255.0) as u8;
+                self.pixels[index as usize + 2] = (b * 255.0) as u8;
+            }
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, r: f32, g: f32, b: f32) {
+        let index = ((y * self.width + x) * 3) as usize;
+        write_pixel(&mut self.pixels[index..index + 3], r, g, b);
+    }
+
+    /// Calls `f` with each row's index and pixels, on several threads when
+    /// built with the `parallel` feature.
+    pub fn par_rows_mut<F>(&mut self, f: F)
+    where
+        F: Fn(u32, &mut CanvasRow) + Sync + Send,
+    {
+        grid::par_chunks_mut(&mut self.pixels, self.width as usize * 3, |y, pixels| {
+            f(y as u32, &mut CanvasRow { pixels })
+        });
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+}
+
+/// One row of a canvas, drawn on its own.
+pub struct CanvasRow<'a> {
+    pixels: &'a mut [u8],
+}
+
+impl CanvasRow<'_> {
+    pub fn set_pixel(&mut self, x: u32, r: f32, g: f32, b: f32) {
+        let index = x as usize * 3;
+        write_pixel(&mut self.pixels[index..index + 3], r, g, b);
+    }
+}
+
+fn write_pixel(pixel: &mut [u8], r: f32, g: f32, b: f32) {
+    pixel[0] = (r * 255.0) as u8;
+    pixel[1] = (g * 255.0) as u8;
+    pixel[2] = (b * 255.0) as u8;
+}
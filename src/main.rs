@@ -1,147 +1,363 @@
-mod scenes;
+use std::env;
+use std::path::Path;
+use std::process;
+use std::sync::mpsc;
 
-use led_matrix_zmq::client::{MatrixClient, MatrixClientSettings};
-use std::time;
+use matryx_generator::audio::{AudioInput, AudioSource};
+use matryx_generator::control::{Control, Event};
+use matryx_generator::gradient::Palette;
+use matryx_generator::mapping::Display;
+use matryx_generator::midi::{Midi, MidiSource, Target};
+use matryx_generator::output::Output;
+use matryx_generator::scenes::plasma::Variant;
+use matryx_generator::scenes::sand::Level;
+use matryx_generator::scenes::wave::Kernel;
+use matryx_generator::scenes::{
+    ExprScene, HourglassScene, PlasmaScene, SandScene, SceneList, WaveScene,
+};
+use matryx_generator::watch::FileWatch;
+use matryx_generator::{osc, Canvas, FrameTimer, Scene};
 
-use scenes::{WaveScene};
+const DEFAULT_ADDR: &str = "tcp://localhost:42024";
 
-const FRAME_TIME: time::Duration = time::Duration::from_millis((1000 / 30) as u64);
+const USAGE: &str = "\
+usage: matryx_generator [--layout panels.layout] [--palette fire|palette.txt]
+                        [--audio stdin|wav:<path>|pipe:<path>]
+                        [--osc 0.0.0.0:9000] [--list-params]
+                        [--midi raw:<path>|port:<name>|virtual] [--midi-map midi.map]
+                        [--midi-learn] [--sand-level level.png]
+                        [--wave-preset waves|blobs|ripples|shimmer]
+                        [--wave-kernel kernel.txt] [--seed <n>]
+                        [--plasma-variant classic|sines|radial|interference|lissajous]
+                        [--expr scene.expr] [--script scene.rhai]
+                        [--plugin scene.wasm]...";
 
-struct FrameTimer {
-    prev_tick: Option<FrameTick>,
+struct Options {
+    layout: Option<String>,
+    palette: Option<Palette>,
+    audio: Option<AudioSource>,
+    osc: Option<String>,
+    midi: Option<MidiSource>,
+    midi_map: String,
+    midi_learn: bool,
+    sand_level: Option<String>,
+    wave_preset: Option<String>,
+    wave_kernel: Option<String>,
+    plasma_variant: Option<Variant>,
+    expr: Option<String>,
+    script: Option<String>,
+    plugins: Vec<String>,
+    seed: Option<u64>,
+    list_params: bool,
 }
 
-#[derive(Copy, Clone, Debug)]
-struct FrameTick {
-    start: time::Instant,
-    instant: time::Instant,
-    delta: time::Duration,
+impl Options {
+    fn from_args() -> Self {
+        let mut options = Options {
+            layout: None,
+            palette: None,
+            audio: None,
+            osc: None,
+            midi: None,
+            midi_map: "midi.map".to_string(),
+            midi_learn: false,
+            sand_level: None,
+            wave_preset: None,
+            wave_kernel: None,
+            plasma_variant: None,
+            expr: None,
+            script: None,
+            plugins: vec![],
+            seed: None,
+            list_params: false,
+        };
+        let mut args = env::args().skip(1);
 
-    t: f32,
-    dt: f32,
-}
-
-impl FrameTick {
-    fn from_start() -> FrameTick {
-        let now = time::Instant::now();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .unwrap_or_else(|| usage_error(format!("{} needs a value", arg)))
+            };
 
-        FrameTick {
-            start: now,
-            instant: now,
-            delta: time::Duration::from_millis(0),
-            t: 0.0,
-            dt: 0.0,
+            match arg.as_str() {
+                "--layout" => options.layout = Some(value()),
+                "--palette" => {
+                    let name = value();
+                    let palette = Palette::named_or_load(&name).unwrap_or_else(|e| {
+                        usage_error(format!(
+                            "failed to load palette {:?} (built-in: {}): {}",
+                            name,
+                            Palette::NAMES.join(", "),
+                            e
+                        ))
+                    });
+                    options.palette = Some(palette);
+                }
+                "--audio" => {
+                    let source = value();
+                    options.audio = Some(AudioSource::parse(&source).unwrap_or_else(|| {
+                        usage_error(format!(
+                            "bad audio source {:?}, expected stdin, wav:<path> or pipe:<path>",
+                            source
+                        ))
+                    }));
+                }
+                "--osc" => options.osc = Some(value()),
+                "--midi" => {
+                    let source = value();
+                    options.midi = Some(MidiSource::parse(&source).unwrap_or_else(|| {
+                        usage_error(format!(
+                            "bad MIDI source {:?}, expected raw:<path>{}",
+                            source,
+                            if cfg!(feature = "midi") {
+                                ", port:<name> or virtual"
+                            } else {
+                                " (build with the `midi` feature for ALSA ports)"
+                            }
+                        ))
+                    }));
+                }
+                "--midi-map" => options.midi_map = value(),
+                "--midi-learn" => options.midi_learn = true,
+                "--sand-level" => options.sand_level = Some(value()),
+                "--wave-preset" => {
+                    let name = value();
+                    if !WaveScene::preset_names().contains(&name.as_str()) {
+                        usage_error(format!(
+                            "unknown wave preset {:?} (built-in: {})",
+                            name,
+                            WaveScene::preset_names().join(", ")
+                        ));
+                    }
+                    options.wave_preset = Some(name);
+                }
+                "--wave-kernel" => options.wave_kernel = Some(value()),
+                "--plasma-variant" => {
+                    let name = value();
+                    let variant = Variant::named(&name).unwrap_or_else(|| {
+                        let names: Vec<_> = Variant::ALL.iter().map(|v| v.name()).collect();
+                        usage_error(format!(
+                            "unknown plasma variant {:?} (built-in: {})",
+                            name,
+                            names.join(", ")
+                        ))
+                    });
+                    options.plasma_variant = Some(variant);
+                }
+                "--expr" => options.expr = Some(value()),
+                "--script" if !cfg!(feature = "scripting") => {
+                    usage_error("--script needs a build with the `scripting` feature".to_string())
+                }
+                "--script" => options.script = Some(value()),
+                "--plugin" if !cfg!(feature = "plugins") => {
+                    usage_error("--plugin needs a build with the `plugins` feature".to_string())
+                }
+                "--plugin" => options.plugins.push(value()),
+                "--seed" => {
+                    let seed = value();
+                    options.seed = Some(seed.parse().unwrap_or_else(|_| {
+                        usage_error(format!("bad seed {:?}, expected a whole number", seed))
+                    }));
+                }
+                "--list-params" => options.list_params = true,
+                "--help" | "-h" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ => usage_error(format!("unknown argument: {}", arg)),
+            }
         }
-    }
 
-    fn from_prev(last_tick: &FrameTick) -> FrameTick {
-        let start = last_tick.start;
-        let instant = time::Instant::now();
-        let delta = last_tick.instant.elapsed();
-        let t = start.elapsed().as_secs_f32();
-        let dt = delta.as_secs_f32();
-
-        FrameTick {
-            start,
-            instant,
-            delta,
-            t,
-            dt,
+        if options.midi_learn && options.midi.is_none() {
+            usage_error("--midi-learn needs a --midi input".to_string());
         }
+
+        options
     }
 }
 
-impl FrameTimer {
-    fn new() -> Self {
-        FrameTimer { prev_tick: None }
-    }
+/// Reports a bad command line and exits.
+fn usage_error(message: String) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
 
-    fn tick(&mut self) -> FrameTick {
-        if self.prev_tick.is_none() {
-            self.prev_tick = Some(FrameTick::from_start());
-            return self.prev_tick.unwrap();
-        } else {
-            self.prev_tick = Some(FrameTick::from_prev(self.prev_tick.as_ref().unwrap()))
-        }
+fn main() {
+    let options = Options::from_args();
 
-        self.prev_tick.unwrap()
+    let display = match &options.layout {
+        Some(path) => Display::load(path, DEFAULT_ADDR).expect("failed to load layout"),
+        None => Display::single(DEFAULT_ADDR, 64, 32),
+    };
+    let mut layout_watch = options.layout.as_ref().map(FileWatch::new);
+
+    let audio = options
+        .audio
+        .map(|source| AudioInput::start(source).expect("failed to start audio input"));
+
+    let (control_tx, controls) = mpsc::channel();
+    if let Some(addr) = options.osc {
+        osc::start(addr, control_tx.clone()).expect("failed to start OSC server");
     }
 
-    fn wait_for_next_frame(&self) {
-        if self.prev_tick.is_none() {
-            return;
-        }
+    let mut output = Output::new(&display);
+    let mut canvas = Canvas::new(display.canvas_width, display.canvas_height);
 
-        let delta = self.prev_tick.unwrap().instant.elapsed();
-        if delta < FRAME_TIME {
-            std::thread::sleep(FRAME_TIME - delta);
-        }
+    let mut frame_timer = FrameTimer::new();
+
+    let mut wave = match options.seed {
+        Some(seed) => WaveScene::seeded(&canvas, seed),
+        None => WaveScene::new(&canvas),
+    };
+    if let Some(name) = options.wave_preset {
+        // Checked against the preset names while reading the arguments.
+        wave = wave.with_preset(&name).unwrap();
+    }
+    if let Some(path) = options.wave_kernel {
+        let kernel = Kernel::load(&path).expect("failed to load wave kernel");
+        wave = wave.with_kernel(kernel);
     }
-}
 
-trait Scene {
-    fn tick(&mut self, _canvas: &mut Canvas, _tick: &FrameTick) {}
-}
+    let mut plasma = PlasmaScene::new();
+    if let Some(variant) = options.plasma_variant {
+        plasma = plasma.with_variant(variant);
+    }
 
-pub struct Canvas {
-    width: u32,
-    height: u32,
-    pixels: Vec<u8>,
-}
+    let mut expr = options
+        .expr
+        .map(|path| ExprScene::load(&path).expect("failed to load expression scene"));
 
-impl Canvas {
-    fn new(width: u32, height: u32) -> Self {
-        Canvas {
-            width,
-            height,
-            pixels: vec![0; (width * height * 3) as usize],
-        }
+    if let Some(palette) = options.palette {
+        wave = wave.with_palette(palette.clone());
+        expr = expr.map(|expr| expr.with_palette(palette.clone()));
+        plasma = plasma.with_palette(palette);
     }
 
-    fn clear(&mut self) {
-        for pixel in self.pixels.iter_mut() {
-            *pixel = 0;
-        }
+    let mut sand = SandScene::new(&canvas);
+    if let Some(path) = options.sand_level {
+        let level = Level::load(&path).expect("failed to load sand level");
+        sand = sand.with_level(&level);
     }
 
-    fn clear_with_color(&mut self, r: f32, g: f32, b: f32) {
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let index = (y * self.width + x) * 3;
-                self.pixels[index as usize] = (r * 255.0) as u8;
-                self.pixels[index as usize + 1] = (g * 255.0) as u8;
-                self.pixels[index as usize + 2] = (b * 255.0) as u8;
+    // Sent as an event, so the current scene can react to the timer.
+    let hourglass_tx = control_tx.clone();
+    let hourglass = HourglassScene::new(&canvas).with_on_end(move || {
+        let _ = hourglass_tx.send(Control::Event(Event {
+            name: "hourglass_finished".to_string(),
+            value: None,
+        }));
+    });
+
+    let mut scenes: Vec<Box<dyn Scene>> = vec![
+        Box::new(wave),
+        Box::new(plasma),
+        Box::new(sand),
+        Box::new(hourglass),
+    ];
+    if let Some(expr) = expr {
+        scenes.push(Box::new(expr));
+    }
+    // Both are refused while reading the arguments unless built in.
+    #[cfg(feature = "scripting")]
+    if let Some(path) = options.script {
+        scenes.push(Box::new(
+            matryx_generator::scenes::ScriptScene::load(&path, &canvas)
+                .expect("failed to load script"),
+        ));
+    }
+    #[cfg(feature = "plugins")]
+    for path in options.plugins {
+        scenes.push(Box::new(
+            matryx_generator::scenes::PluginScene::load(&path, &canvas)
+                .unwrap_or_else(|e| panic!("failed to load plugin {:?}: {}", path, e)),
+        ));
+    }
+    let mut scenes = SceneList::new(scenes);
+
+    if options.list_params {
+        for scene in scenes.iter() {
+            for param in scene.params() {
+                println!(
+                    "/param/{}/{}\t{} ({}..{})",
+                    scene.name(),
+                    param.name,
+                    param.value,
+                    param.min,
+                    param.max
+                );
             }
         }
+        return;
     }
 
-    fn set_pixel(&mut self, x: u32, y: u32, r: f32, g: f32, b: f32) {
-        let index = ((y * self.width + x) * 3) as usize;
-        self.pixels[index] = (r * 255.0) as u8;
-        self.pixels[index + 1] = (g * 255.0) as u8;
-        self.pixels[index + 2] = (b * 255.0) as u8;
+    let midi_map = options.midi_map;
+    let midi = options.midi.map(|source| {
+        Midi::start(source, &midi_map, control_tx.clone()).expect("failed to start MIDI input")
+    });
+
+    if options.midi_learn {
+        // Refused without `--midi` while reading the arguments.
+        let midi = midi.as_ref().unwrap();
+        midi.learn(learn_targets(&scenes));
     }
 
-    fn pixels(&self) -> &[u8] {
-        &self.pixels
+    loop {
+        let mut tick = frame_timer.tick();
+        if let Some(audio) = &audio {
+            tick.audio = audio.frame();
+        }
+
+        if let Some(watch) = &mut layout_watch {
+            if watch.changed(tick.dt) {
+                reload_layout(watch.path(), &mut output, &mut canvas, &mut scenes);
+            }
+        }
+
+        for control in controls.try_iter() {
+            control.apply(&mut scenes, &mut output);
+        }
+
+        scenes.current().tick(&mut canvas, &tick);
+        output.send(&canvas);
+
+        frame_timer.wait_for_next_frame();
     }
 }
 
-fn main() {
-    let client = MatrixClient::new(MatrixClientSettings {
-        addr: "tcp://localhost:42024".to_string(),
-    });
+/// Switches to the layout in `path`, resizing the canvas and every scene to
+/// it. A broken file is reported and the current layout kept.
+fn reload_layout(path: &Path, output: &mut Output, canvas: &mut Canvas, scenes: &mut SceneList) {
+    let display = match Display::load(path, DEFAULT_ADDR) {
+        Ok(display) => display,
+        Err(e) => return eprintln!("layout: {}: {}", path.display(), e),
+    };
 
-    let mut canvas = Canvas::new(64, 32);
-    let mut frame_timer = FrameTimer::new();
-    let mut scene = WaveScene::new(&canvas);
+    output.set_display(&display);
+    let (width, height) = (display.canvas_width, display.canvas_height);
+    if (width, height) != (canvas.width(), canvas.height()) {
+        canvas.resize(width, height);
+        scenes.resize(width, height);
+    }
+    println!("layout: reloaded {}, {}x{}", path.display(), width, height);
+}
 
-    loop {
-        let tick = frame_timer.tick();
+/// Everything a MIDI control can be bound to, in the order learn mode asks
+/// for them.
+fn learn_targets(scenes: &SceneList) -> Vec<Target> {
+    let mut targets = vec![Target::NextScene, Target::PrevScene, Target::Brightness];
 
-        scene.tick(&mut canvas, &tick);
-        client.send_frame(canvas.pixels());
+    for scene in scenes.iter() {
+        targets.push(Target::Scene(scene.name().to_string()));
+    }
 
-        frame_timer.wait_for_next_frame();
+    for scene in scenes.iter() {
+        for param in scene.params() {
+            targets.push(Target::Param {
+                scene: scene.name().to_string(),
+                name: param.name.to_string(),
+            });
+        }
     }
+
+    targets
 }
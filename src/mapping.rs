@@ -0,0 +1,839 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Clockwise rotation applied to a block of pixels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Rotation {
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Rotation {
+    fn parse(s: &str) -> Option<Rotation> {
+        match s {
+            "0" | "r0" => Some(Rotation::None),
+            "90" | "r90" => Some(Rotation::Cw90),
+            "180" | "r180" => Some(Rotation::Cw180),
+            "270" | "r270" => Some(Rotation::Cw270),
+            _ => None,
+        }
+    }
+}
+
+/// Orientation of a block of pixels: a rotation followed by optional flips.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+impl Orientation {
+    pub const IDENTITY: Orientation = Orientation {
+        rotation: Rotation::None,
+        flip_x: false,
+        flip_y: false,
+    };
+
+    /// Size of a `width` x `height` block once it has been oriented.
+    pub fn oriented_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self.rotation {
+            Rotation::Cw90 | Rotation::Cw270 => (height, width),
+            _ => (width, height),
+        }
+    }
+
+    /// Maps a position in the oriented block back to the source block.
+    fn source(&self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        let (out_w, out_h) = self.oriented_size(width, height);
+        let x = if self.flip_x { out_w - 1 - x } else { x };
+        let y = if self.flip_y { out_h - 1 - y } else { y };
+
+        match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Cw90 => (y, height - 1 - x),
+            Rotation::Cw180 => (width - 1 - x, height - 1 - y),
+            Rotation::Cw270 => (width - 1 - y, x),
+        }
+    }
+
+    fn parse(words: &[&str]) -> Option<Orientation> {
+        let mut orientation = Orientation::IDENTITY;
+
+        for word in words {
+            match *word {
+                "flip-x" => orientation.flip_x = true,
+                "flip-y" => orientation.flip_y = true,
+                w => orientation.rotation = Rotation::parse(w)?,
+            }
+        }
+
+        Some(orientation)
+    }
+}
+
+/// Lookup table from output frame pixels to logical canvas pixels.
+///
+/// Every output pixel either names a canvas pixel to copy from, or is left
+/// black.
+#[derive(Clone, Debug)]
+pub struct PixelMap {
+    source: Vec<Option<u32>>,
+}
+
+impl PixelMap {
+    pub fn identity(width: u32, height: u32) -> Self {
+        PixelMap::oriented(width, height, Orientation::IDENTITY)
+    }
+
+    /// Rotates and flips a whole `width` x `height` canvas.
+    pub fn oriented(width: u32, height: u32, orientation: Orientation) -> Self {
+        let (out_w, out_h) = orientation.oriented_size(width, height);
+        let mut source = Vec::with_capacity((out_w * out_h) as usize);
+
+        for y in 0..out_h {
+            for x in 0..out_w {
+                let (sx, sy) = orientation.source(x, y, width, height);
+                source.push(Some(sy * width + sx));
+            }
+        }
+
+        PixelMap { source }
+    }
+
+    pub fn from_layout(layout: &Layout) -> Self {
+        let (out_w, out_h) = layout.output_size();
+        let (view_w, view_h) = layout
+            .orientation
+            .oriented_size(layout.canvas_width, layout.canvas_height);
+        let mut source = vec![None; (out_w * out_h) as usize];
+
+        for (i, panel) in layout.panels.iter().enumerate() {
+            let chain_x = i as u32 * layout.panel_width;
+            let (area_w, area_h) = panel
+                .orientation
+                .oriented_size(layout.panel_width, layout.panel_height);
+
+            for y in 0..layout.panel_height {
+                for x in 0..layout.panel_width {
+                    // The panel's own pixels are its area of the oriented
+                    // canvas with the panel's orientation applied.
+                    let (ax, ay) = panel.orientation.source(x, y, area_w, area_h);
+                    let vx = panel.x + ax;
+                    let vy = panel.y + ay;
+
+                    if vx < view_w && vy < view_h {
+                        let (cx, cy) = layout.orientation.source(
+                            vx,
+                            vy,
+                            layout.canvas_width,
+                            layout.canvas_height,
+                        );
+                        let out = (y * out_w + chain_x + x) as usize;
+                        source[out] = Some(cy * layout.canvas_width + cx);
+                    }
+                }
+            }
+        }
+
+        for remap in &layout.remaps {
+            if remap.out_x < out_w && remap.out_y < out_h {
+                let out = (remap.out_y * out_w + remap.out_x) as usize;
+                source[out] = remap.canvas.map(|(x, y)| y * layout.canvas_width + x);
+            }
+        }
+
+        PixelMap { source }
+    }
+
+    /// Maps a screen showing `region` of a `canvas_width` wide canvas, with
+    /// its panels described by `layout`.
+    pub fn from_screen(screen: &Screen, canvas_width: u32) -> Self {
+        let mut map = PixelMap::from_layout(&screen.layout);
+        let region = &screen.region;
+
+        for source in map.source.iter_mut().flatten() {
+            let x = *source % screen.layout.canvas_width + region.x;
+            let y = *source / screen.layout.canvas_width + region.y;
+            *source = y * canvas_width + x;
+        }
+
+        map
+    }
+
+    /// Copies RGB pixels from `canvas` into `frame` using this map.
+    pub fn apply(&self, canvas: &[u8], frame: &mut Vec<u8>) {
+        frame.clear();
+        frame.reserve(self.source.len() * 3);
+
+        for source in &self.source {
+            match source {
+                Some(i) => {
+                    let i = *i as usize * 3;
+                    frame.extend_from_slice(&canvas[i..i + 3]);
+                }
+                None => frame.extend_from_slice(&[0, 0, 0]),
+            }
+        }
+    }
+}
+
+/// A physical panel in the chain, showing the area of the canvas starting
+/// at (`x`, `y`).
+#[derive(Copy, Clone, Debug)]
+pub struct Panel {
+    pub x: u32,
+    pub y: u32,
+    pub orientation: Orientation,
+}
+
+/// Sends an output pixel to an arbitrary canvas pixel, or blanks it.
+#[derive(Copy, Clone, Debug)]
+pub struct Remap {
+    pub out_x: u32,
+    pub out_y: u32,
+    pub canvas: Option<(u32, u32)>,
+}
+
+/// Describes how the logical canvas is spread over a chain of panels.
+///
+/// The canvas is first rotated and flipped by `orientation`, then panels pick
+/// their areas out of the result. Panels are listed in chain order. The
+/// output frame is every panel's pixels laid side by side, which is what the
+/// matrix server expects for a single chain.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+    pub orientation: Orientation,
+    pub panel_width: u32,
+    pub panel_height: u32,
+    pub panels: Vec<Panel>,
+    pub remaps: Vec<Remap>,
+}
+
+impl Layout {
+    /// A `cols` x `rows` grid of panels, chained row by row. With
+    /// `serpentine`, every other row runs right-to-left and is mounted
+    /// upside down, as is usual when the cable snakes back.
+    pub fn grid(
+        panel_width: u32,
+        panel_height: u32,
+        cols: u32,
+        rows: u32,
+        serpentine: bool,
+    ) -> Self {
+        let mut panels = Vec::new();
+
+        for row in 0..rows {
+            let reversed = serpentine && row % 2 == 1;
+
+            for i in 0..cols {
+                let col = if reversed { cols - 1 - i } else { i };
+                let rotation = if reversed {
+                    Rotation::Cw180
+                } else {
+                    Rotation::None
+                };
+
+                panels.push(Panel {
+                    x: col * panel_width,
+                    y: row * panel_height,
+                    orientation: Orientation {
+                        rotation,
+                        ..Orientation::IDENTITY
+                    },
+                });
+            }
+        }
+
+        Layout {
+            canvas_width: cols * panel_width,
+            canvas_height: rows * panel_height,
+            orientation: Orientation::IDENTITY,
+            panel_width,
+            panel_height,
+            panels,
+            remaps: Vec::new(),
+        }
+    }
+
+    pub fn output_size(&self) -> (u32, u32) {
+        (
+            self.panel_width * self.panels.len() as u32,
+            self.panel_height,
+        )
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Layout> {
+        Layout::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses a layout file. Each line is one directive, `#` starts a
+    /// comment:
+    ///
+    /// ```text
+    /// canvas 128 64            # logical canvas size
+    /// orientation r90 flip-x   # turn the whole canvas before placing panels
+    /// panel 64 32              # size of a single panel
+    /// grid 2 2 serpentine      # or list panels one by one, in chain order:
+    /// chain 0 0 r0             # canvas x, canvas y, rotation, [flip-x] [flip-y]
+    /// remap 10 3 0 0           # output x, output y, canvas x, canvas y
+    /// remap 11 3 off           # output x, output y, left black
+    /// ```
+    pub fn parse(text: &str) -> io::Result<Layout> {
+        Layout::parse_lines(text.lines().enumerate(), None)
+    }
+
+    /// Parses numbered lines of a layout, starting from a `canvas` size if
+    /// one is known already. Line numbers count from 0.
+    fn parse_lines<'a, I>(lines: I, mut canvas: Option<(u32, u32)>) -> io::Result<Layout>
+    where
+        I: IntoIterator<Item = (usize, &'a str)>,
+    {
+        let mut orientation = Orientation::IDENTITY;
+        let mut panel = None;
+        let mut grid = None;
+        let mut panels = Vec::new();
+        let mut remaps = Vec::new();
+
+        for (n, line) in lines {
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let bad_line = || invalid(format!("line {}: can't parse {:?}", n + 1, line.trim()));
+
+            match words.as_slice() {
+                [] => {}
+                ["canvas", w, h] => canvas = Some((number(w, n)?, number(h, n)?)),
+                ["orientation", rest @ ..] => {
+                    orientation = Orientation::parse(rest).ok_or_else(bad_line)?
+                }
+                ["panel", w, h] => panel = Some((number(w, n)?, number(h, n)?)),
+                ["grid", cols, rows, rest @ ..] => {
+                    let serpentine = match rest {
+                        [] => false,
+                        ["serpentine"] => true,
+                        _ => return Err(bad_line()),
+                    };
+                    grid = Some((number(cols, n)?, number(rows, n)?, serpentine));
+                }
+                ["chain", x, y, rest @ ..] => panels.push(Panel {
+                    x: number(x, n)?,
+                    y: number(y, n)?,
+                    orientation: Orientation::parse(rest).ok_or_else(bad_line)?,
+                }),
+                ["remap", out_x, out_y, "off"] => remaps.push(Remap {
+                    out_x: number(out_x, n)?,
+                    out_y: number(out_y, n)?,
+                    canvas: None,
+                }),
+                ["remap", out_x, out_y, x, y] => remaps.push(Remap {
+                    out_x: number(out_x, n)?,
+                    out_y: number(out_y, n)?,
+                    canvas: Some((number(x, n)?, number(y, n)?)),
+                }),
+                _ => return Err(bad_line()),
+            }
+        }
+
+        let (panel_width, panel_height) =
+            panel.ok_or_else(|| invalid("layout is missing a `panel` line".to_string()))?;
+
+        let mut layout = match grid {
+            Some((cols, rows, serpentine)) => {
+                if cols.checked_mul(panel_width).is_none()
+                    || rows.checked_mul(panel_height).is_none()
+                {
+                    return Err(invalid(format!(
+                        "grid of {}x{} panels is too large",
+                        cols, rows
+                    )));
+                }
+                Layout::grid(panel_width, panel_height, cols, rows, serpentine)
+            }
+            None => Layout {
+                canvas_width: 0,
+                canvas_height: 0,
+                orientation: Orientation::IDENTITY,
+                panel_width,
+                panel_height,
+                panels: Vec::new(),
+                remaps: Vec::new(),
+            },
+        };
+
+        layout.panels.extend(panels);
+        layout.remaps = remaps;
+        layout.orientation = orientation;
+
+        match canvas {
+            Some((w, h)) => {
+                layout.canvas_width = w;
+                layout.canvas_height = h;
+            }
+            None if grid.is_none() => {
+                return Err(invalid(
+                    "layout needs a `canvas` line when not using `grid`".to_string(),
+                ))
+            }
+            None => {
+                let (w, h) = orientation.oriented_size(layout.canvas_width, layout.canvas_height);
+                layout.canvas_width = w;
+                layout.canvas_height = h;
+            }
+        }
+
+        check_canvas(layout.canvas_width, layout.canvas_height)?;
+
+        if layout.panels.is_empty() {
+            return Err(invalid("layout has no panels".to_string()));
+        }
+
+        for panel in &layout.panels {
+            let (area_w, area_h) = panel.orientation.oriented_size(panel_width, panel_height);
+            if panel.x.checked_add(area_w).is_none() || panel.y.checked_add(area_h).is_none() {
+                return Err(invalid(format!(
+                    "panel at ({}, {}) is too far out",
+                    panel.x, panel.y
+                )));
+            }
+        }
+
+        let frame_size = u32::try_from(layout.panels.len())
+            .ok()
+            .and_then(|panels| panels.checked_mul(panel_width))
+            .and_then(|width| width.checked_mul(panel_height));
+        if frame_size.is_none() {
+            return Err(invalid(format!(
+                "{} panels make the output frame too large",
+                layout.panels.len()
+            )));
+        }
+
+        let (out_w, out_h) = layout.output_size();
+        for remap in &layout.remaps {
+            if remap.out_x >= out_w || remap.out_y >= out_h {
+                return Err(invalid(format!(
+                    "remap from ({}, {}) is outside the {}x{} output frame",
+                    remap.out_x, remap.out_y, out_w, out_h
+                )));
+            }
+            if let Some((x, y)) = remap.canvas {
+                if x >= layout.canvas_width || y >= layout.canvas_height {
+                    return Err(invalid(format!(
+                        "remap to ({}, {}) is outside the canvas",
+                        x, y
+                    )));
+                }
+            }
+        }
+
+        Ok(layout)
+    }
+}
+
+/// A rectangle of the canvas, in canvas pixels.
+#[derive(Copy, Clone, Debug)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// One matrix server, showing a region of the canvas on its own panels.
+#[derive(Clone, Debug)]
+pub struct Screen {
+    pub addr: String,
+    pub region: Region,
+    pub layout: Layout,
+}
+
+/// The whole logical canvas and the matrix servers it is split across.
+#[derive(Clone, Debug)]
+pub struct Display {
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+    pub screens: Vec<Screen>,
+}
+
+impl Display {
+    /// A single server showing the whole canvas on one panel.
+    pub fn single(addr: &str, width: u32, height: u32) -> Self {
+        Display::from_layout(addr, Layout::grid(width, height, 1, 1, false))
+    }
+
+    pub fn from_layout(addr: &str, layout: Layout) -> Self {
+        Display {
+            canvas_width: layout.canvas_width,
+            canvas_height: layout.canvas_height,
+            screens: vec![Screen {
+                addr: addr.to_string(),
+                region: Region {
+                    x: 0,
+                    y: 0,
+                    width: layout.canvas_width,
+                    height: layout.canvas_height,
+                },
+                layout,
+            }],
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, default_addr: &str) -> io::Result<Display> {
+        Display::parse(&fs::read_to_string(path)?, default_addr)
+    }
+
+    /// Parses a layout file that may split the canvas across servers.
+    ///
+    /// A file without `server` lines is a single [`Layout`] sent to
+    /// `default_addr`. Otherwise the file starts with the `canvas` size, and
+    /// each `server` line starts a section with the `region` of the canvas
+    /// that server shows, followed by its own layout. Panel positions in a
+    /// section are relative to its region.
+    ///
+    /// ```text
+    /// canvas 128 64
+    ///
+    /// server tcp://pi-left:42024
+    /// region 0 0 64 64
+    /// panel 64 32
+    /// grid 1 2 serpentine
+    ///
+    /// server tcp://pi-right:42024
+    /// region 64 0 64 64
+    /// panel 64 32
+    /// grid 1 2 serpentine
+    /// ```
+    pub fn parse(text: &str, default_addr: &str) -> io::Result<Display> {
+        let is_server = |line: &&str| line.split_whitespace().next() == Some("server");
+        if !text.lines().any(|line| is_server(&line)) {
+            return Ok(Display::from_layout(default_addr, Layout::parse(text)?));
+        }
+
+        let mut canvas = None;
+        let mut sections: Vec<(String, Option<Region>, NumberedLines)> = Vec::new();
+
+        for (n, line) in text.lines().enumerate() {
+            let words: Vec<&str> = line
+                .split('#')
+                .next()
+                .unwrap_or("")
+                .split_whitespace()
+                .collect();
+
+            match (words.as_slice(), sections.last_mut()) {
+                (["server", addr], _) => sections.push((addr.to_string(), None, Vec::new())),
+                (["canvas", w, h], None) => canvas = Some((number(w, n)?, number(h, n)?)),
+                (["region", x, y, w, h], Some(section)) => {
+                    section.1 = Some(Region {
+                        x: number(x, n)?,
+                        y: number(y, n)?,
+                        width: number(w, n)?,
+                        height: number(h, n)?,
+                    })
+                }
+                ([], _) => {}
+                (_, Some(section)) => section.2.push((n, line)),
+                (_, None) => {
+                    return Err(invalid(format!(
+                        "line {}: expected `canvas` or `server`, got {:?}",
+                        n + 1,
+                        line.trim()
+                    )))
+                }
+            }
+        }
+
+        let (canvas_width, canvas_height) =
+            canvas.ok_or_else(|| invalid("layout is missing a `canvas` line".to_string()))?;
+        check_canvas(canvas_width, canvas_height)?;
+
+        let mut screens = Vec::new();
+        for (addr, region, lines) in sections {
+            let region = region
+                .ok_or_else(|| invalid(format!("server {} is missing a `region` line", addr)))?;
+
+            let right = region.x.checked_add(region.width);
+            let bottom = region.y.checked_add(region.height);
+            if right.is_none_or(|right| right > canvas_width)
+                || bottom.is_none_or(|bottom| bottom > canvas_height)
+            {
+                return Err(invalid(format!(
+                    "region of server {} is outside the canvas",
+                    addr
+                )));
+            }
+
+            let layout = Layout::parse_lines(lines, Some((region.width, region.height)))
+                .map_err(|e| invalid(format!("server {}: {}", addr, e)))?;
+
+            screens.push(Screen {
+                addr,
+                region,
+                layout,
+            });
+        }
+
+        Ok(Display {
+            canvas_width,
+            canvas_height,
+            screens,
+        })
+    }
+}
+
+/// Lines of a layout file along with their line numbers, counting from 0.
+type NumberedLines<'a> = Vec<(usize, &'a str)>;
+
+/// Scenes need at least one pixel to draw on.
+fn check_canvas(width: u32, height: u32) -> io::Result<()> {
+    if width == 0 || height == 0 {
+        return Err(invalid(format!("canvas {}x{} is empty", width, height)));
+    }
+    Ok(())
+}
+
+fn number(s: &str, line: usize) -> io::Result<u32> {
+    s.parse()
+        .map_err(|_| invalid(format!("line {}: expected a number, got {:?}", line + 1, s)))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The source of each pixel of a 3x2 block, oriented, row by row.
+    fn orient(words: &[&str]) -> Vec<u32> {
+        let orientation = Orientation::parse(words).unwrap();
+        PixelMap::oriented(3, 2, orientation)
+            .source
+            .iter()
+            .map(|source| source.unwrap())
+            .collect()
+    }
+
+    /// The canvas pixel each output pixel shows, for a canvas whose pixels
+    /// are numbered.
+    fn frame(layout: &Layout) -> Vec<u8> {
+        let canvas: Vec<u8> = (0..layout.canvas_width * layout.canvas_height)
+            .flat_map(|i| vec![i as u8; 3])
+            .collect();
+        let mut frame = Vec::new();
+        PixelMap::from_layout(layout).apply(&canvas, &mut frame);
+
+        frame.iter().step_by(3).copied().collect()
+    }
+
+    fn error(text: &str) -> String {
+        Layout::parse(text).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn orientations() {
+        assert_eq!(orient(&[]), [0, 1, 2, 3, 4, 5]);
+        assert_eq!(orient(&["r90"]), [3, 0, 4, 1, 5, 2]);
+        assert_eq!(orient(&["r180"]), [5, 4, 3, 2, 1, 0]);
+        assert_eq!(orient(&["r270"]), [2, 5, 1, 4, 0, 3]);
+        assert_eq!(orient(&["flip-x"]), [2, 1, 0, 5, 4, 3]);
+        assert_eq!(orient(&["flip-y"]), [3, 4, 5, 0, 1, 2]);
+        assert_eq!(orient(&["90", "flip-x"]), [0, 3, 1, 4, 2, 5]);
+        assert_eq!(orient(&["r0", "flip-x", "flip-y"]), orient(&["r180"]));
+        assert!(Orientation::parse(&["r45"]).is_none());
+    }
+
+    #[test]
+    fn serpentine_rows_run_back_upside_down() {
+        let layout = Layout::grid(2, 1, 2, 2, true);
+        assert_eq!(frame(&layout), [0, 1, 2, 3, 7, 6, 5, 4]);
+    }
+
+    #[test]
+    fn parses_layouts() {
+        let layout = Layout::parse(
+            "# two panels, turned\n\
+             canvas 2 4\n\
+             orientation r90\n\
+             panel 2 2\n\
+             chain 0 0\n\
+             chain 2 0 r180 # upside down\n\
+             remap 0 0 off\n\
+             remap 3 1 0 0\n",
+        )
+        .unwrap();
+
+        assert_eq!(layout.output_size(), (4, 2));
+        // Turned, the canvas is 4x2: 6 4 2 0 over 7 5 3 1.
+        assert_eq!(frame(&layout), [0, 4, 1, 3, 7, 5, 0, 0]);
+    }
+
+    /// The canvas pixel each pixel sent to each server shows, for a canvas
+    /// whose pixels are numbered.
+    fn screen_frames(display: &Display) -> Vec<Vec<u8>> {
+        let canvas: Vec<u8> = (0..display.canvas_width * display.canvas_height)
+            .flat_map(|i| vec![i as u8; 3])
+            .collect();
+
+        display
+            .screens
+            .iter()
+            .map(|screen| {
+                let mut frame = Vec::new();
+                PixelMap::from_screen(screen, display.canvas_width).apply(&canvas, &mut frame);
+                frame.iter().step_by(3).copied().collect()
+            })
+            .collect()
+    }
+
+    fn display_error(text: &str) -> String {
+        Display::parse(text, "tcp://default")
+            .err()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn splits_canvas_across_servers() {
+        let display = Display::parse(
+            "canvas 4 2\n\
+             \n\
+             server tcp://left:42024\n\
+             region 0 0 2 2\n\
+             panel 2 1\n\
+             grid 1 2\n\
+             \n\
+             server tcp://right:42024\n\
+             region 2 0 2 2\n\
+             panel 2 2\n\
+             grid 1 1\n\
+             orientation flip-x\n",
+            "tcp://default",
+        )
+        .unwrap();
+
+        assert_eq!((display.canvas_width, display.canvas_height), (4, 2));
+        let addrs: Vec<&str> = display.screens.iter().map(|s| s.addr.as_str()).collect();
+        assert_eq!(addrs, ["tcp://left:42024", "tcp://right:42024"]);
+
+        // The canvas is 0 1 2 3 over 4 5 6 7.
+        let frames = screen_frames(&display);
+        assert_eq!(frames[0], [0, 1, 4, 5]);
+        assert_eq!(frames[1], [3, 2, 7, 6]);
+    }
+
+    #[test]
+    fn single_layouts_go_to_the_default_server() {
+        let display = Display::parse("panel 2 1\ngrid 2 1\n", "tcp://default").unwrap();
+
+        assert_eq!(display.screens.len(), 1);
+        assert_eq!(display.screens[0].addr, "tcp://default");
+        assert_eq!(screen_frames(&display), [[0, 1, 2, 3]]);
+    }
+
+    #[test]
+    fn rejects_bad_displays() {
+        assert_eq!(
+            display_error("server tcp://a\nregion 0 0 1 1\npanel 1 1\ngrid 1 1\n"),
+            "layout is missing a `canvas` line"
+        );
+        assert_eq!(
+            display_error("canvas 2 2\nserver tcp://a\npanel 1 1\ngrid 1 1\n"),
+            "server tcp://a is missing a `region` line"
+        );
+        assert_eq!(
+            display_error("canvas 2 2\nserver tcp://a\nregion 1 0 2 2\npanel 2 2\ngrid 1 1\n"),
+            "region of server tcp://a is outside the canvas"
+        );
+        assert_eq!(
+            display_error("canvas 2 2\nserver tcp://a\nregion 4294967295 0 2 2\n"),
+            "region of server tcp://a is outside the canvas"
+        );
+        assert_eq!(
+            display_error("panel 1 1\nserver tcp://a\n"),
+            "line 1: expected `canvas` or `server`, got \"panel 1 1\""
+        );
+        // Line numbers count from the top of the file, not the section.
+        assert_eq!(
+            display_error(
+                "canvas 4 2\n\
+                 server tcp://a\n\
+                 region 0 0 2 2\n\
+                 panel 2 2\n\
+                 grid 1 1\n\
+                 server tcp://b\n\
+                 region 2 0 2 2\n\
+                 panel 2 2\n\
+                 wobble\n"
+            ),
+            "server tcp://b: line 9: can't parse \"wobble\""
+        );
+        assert_eq!(
+            display_error("canvas 0 0\nserver tcp://a\nregion 0 0 0 0\n"),
+            "canvas 0x0 is empty"
+        );
+        assert_eq!(
+            display_error("canvas 2 2\nserver tcp://a\nregion 0 0 0 2\npanel 1 1\n"),
+            "server tcp://a: canvas 0x2 is empty"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_layouts() {
+        assert_eq!(error("canvas 4 4\n"), "layout is missing a `panel` line");
+        assert_eq!(
+            error("panel 2 2\nchain 0 0\n"),
+            "layout needs a `canvas` line when not using `grid`"
+        );
+        assert_eq!(error("canvas 4 4\npanel 2 2\n"), "layout has no panels");
+        assert_eq!(
+            error("panel 2 2\ngrid 1 1\nwobble\n"),
+            "line 3: can't parse \"wobble\""
+        );
+        assert_eq!(error("panel 2 x\n"), "line 1: expected a number, got \"x\"");
+        assert_eq!(
+            error("panel 2 2\ngrid 1 1 zigzag\n"),
+            "line 2: can't parse \"grid 1 1 zigzag\""
+        );
+        assert_eq!(
+            error("panel 2 2\ngrid 1 1\nchain 0 0 r45\n"),
+            "line 3: can't parse \"chain 0 0 r45\""
+        );
+        assert_eq!(
+            error("panel 2 2\ngrid 1 1\nremap 0 0 2 0\n"),
+            "remap to (2, 0) is outside the canvas"
+        );
+        assert_eq!(
+            error("panel 2 2\ngrid 1 1\nremap 2 0 off\n"),
+            "remap from (2, 0) is outside the 2x2 output frame"
+        );
+        assert_eq!(
+            error("canvas 0 4\npanel 2 2\nchain 0 0\n"),
+            "canvas 0x4 is empty"
+        );
+        assert_eq!(error("panel 2 0\ngrid 1 1\n"), "canvas 2x0 is empty");
+        assert_eq!(
+            error("canvas 4 4\npanel 2 2\nchain 4294967295 0\n"),
+            "panel at (4294967295, 0) is too far out"
+        );
+        assert_eq!(
+            error("canvas 4 4\npanel 2 4\nchain 0 4294967294 r90\n"),
+            "panel at (0, 4294967294) is too far out"
+        );
+        assert_eq!(
+            error("panel 65536 1\ngrid 65536 1\n"),
+            "grid of 65536x1 panels is too large"
+        );
+        assert_eq!(
+            error("panel 65536 1\ngrid 1 65536\n"),
+            "65536 panels make the output frame too large"
+        );
+    }
+}
@@ -0,0 +1,536 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::control::Control;
+
+/// Where MIDI comes from.
+#[derive(Clone, Debug)]
+pub enum MidiSource {
+    /// Raw MIDI bytes from a device such as `/dev/snd/midiC1D0`, or from a
+    /// named pipe for testing.
+    Raw(String),
+    /// An ALSA sequencer port whose name contains the given text.
+    #[cfg(feature = "midi")]
+    Port(String),
+    /// A virtual ALSA sequencer port called `matryx_generator`, which other
+    /// software connects to.
+    #[cfg(feature = "midi")]
+    Virtual,
+}
+
+impl MidiSource {
+    /// Parses `raw:<path>`, and with the `midi` feature, `port:<name>` or
+    /// `virtual`.
+    pub fn parse(s: &str) -> Option<MidiSource> {
+        if let Some(path) = s.strip_prefix("raw:") {
+            return Some(MidiSource::Raw(path.to_string()));
+        }
+
+        #[cfg(feature = "midi")]
+        {
+            if s == "virtual" {
+                return Some(MidiSource::Virtual);
+            } else if let Some(name) = s.strip_prefix("port:") {
+                return Some(MidiSource::Port(name.to_string()));
+            }
+        }
+
+        None
+    }
+}
+
+/// A physical control: a CC knob or fader, or a note pad or key.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Input {
+    Cc { channel: u8, number: u8 },
+    Note { channel: u8, number: u8 },
+}
+
+/// What a bound control does.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    Param { scene: String, name: String },
+    NextScene,
+    PrevScene,
+    Scene(String),
+    Brightness,
+}
+
+impl Target {
+    fn is_button(&self) -> bool {
+        !matches!(self, Target::Param { .. } | Target::Brightness)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Param { scene, name } => write!(f, "param {} {}", scene, name),
+            Target::NextScene => write!(f, "next"),
+            Target::PrevScene => write!(f, "prev"),
+            Target::Scene(name) => write!(f, "scene {}", name),
+            Target::Brightness => write!(f, "brightness"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Binding {
+    input: Input,
+    target: Target,
+}
+
+/// Bindings from MIDI controls to generator controls, stored in a mapping
+/// file with one binding per line:
+///
+/// ```text
+/// cc 0 21 param wave hue_speed    # channel, controller, target
+/// cc 0 7 brightness
+/// note 9 36 next                  # channel, note, target
+/// note 9 37 scene plasma
+/// ```
+///
+/// Channels count from 0.
+#[derive(Clone, Debug, Default)]
+pub struct Bindings {
+    bindings: Vec<Binding>,
+}
+
+impl Bindings {
+    /// Loads bindings, or starts with none if the file doesn't exist yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Bindings> {
+        match fs::read_to_string(path) {
+            Ok(text) => Bindings::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Bindings::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut text = String::new();
+        for binding in &self.bindings {
+            let (kind, channel, number) = match binding.input {
+                Input::Cc { channel, number } => ("cc", channel, number),
+                Input::Note { channel, number } => ("note", channel, number),
+            };
+            text += &format!("{} {} {} {}\n", kind, channel, number, binding.target);
+        }
+
+        fs::write(path, text)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Bindings> {
+        let mut bindings = Vec::new();
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let bad_line = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: can't parse {:?}", n + 1, line.trim()),
+                )
+            };
+
+            if words.is_empty() {
+                continue;
+            }
+
+            let (kind, channel, number, target) = match words.as_slice() {
+                [kind, channel, number, target @ ..] => (*kind, *channel, *number, target),
+                _ => return Err(bad_line()),
+            };
+
+            let channel: u8 = channel.parse().map_err(|_| bad_line())?;
+            let number: u8 = number.parse().map_err(|_| bad_line())?;
+            let input = match kind {
+                "cc" => Input::Cc { channel, number },
+                "note" => Input::Note { channel, number },
+                _ => return Err(bad_line()),
+            };
+
+            let target = match target {
+                ["param", scene, name] => Target::Param {
+                    scene: scene.to_string(),
+                    name: name.to_string(),
+                },
+                ["next"] => Target::NextScene,
+                ["prev"] => Target::PrevScene,
+                ["scene", name] => Target::Scene(name.to_string()),
+                ["brightness"] => Target::Brightness,
+                _ => return Err(bad_line()),
+            };
+
+            bindings.push(Binding { input, target });
+        }
+
+        Ok(Bindings { bindings })
+    }
+
+    /// Binds `input` to `target`, replacing whatever either was bound to.
+    pub fn bind(&mut self, input: Input, target: Target) {
+        self.bindings
+            .retain(|binding| binding.input != input && binding.target != target);
+        self.bindings.push(Binding { input, target });
+    }
+
+    fn control(&self, input: Input, value: u8) -> Option<Control> {
+        let binding = self
+            .bindings
+            .iter()
+            .find(|binding| binding.input == input)?;
+
+        // Buttons fire when pressed, not when released.
+        if binding.target.is_button() && value < 64 {
+            return None;
+        }
+
+        let value = value as f32 / 127.0;
+        let control = match &binding.target {
+            Target::Param { scene, name } => Control::SetParam {
+                scene: Some(scene.clone()),
+                name: name.clone(),
+                value,
+            },
+            Target::NextScene => Control::NextScene,
+            Target::PrevScene => Control::PrevScene,
+            Target::Scene(name) => Control::SelectScene(name.clone()),
+            Target::Brightness => Control::Brightness(value),
+        };
+
+        Some(control)
+    }
+}
+
+/// Parses a MIDI byte stream into control and note events.
+#[derive(Default)]
+struct Parser {
+    status: u8,
+    data: Vec<u8>,
+}
+
+impl Parser {
+    /// Feeds one byte, returning an input and its value once a message is
+    /// complete.
+    fn push(&mut self, byte: u8) -> Option<(Input, u8)> {
+        if byte >= 0xf8 {
+            // Real-time messages can show up anywhere, and don't matter.
+            return None;
+        }
+
+        if byte & 0x80 != 0 {
+            self.status = byte;
+            self.data.clear();
+            return None;
+        }
+
+        // Data bytes reuse the last status, so running status works.
+        let len = match self.status & 0xf0 {
+            0xc0 | 0xd0 => 1,
+            // System exclusive and common messages aren't needed.
+            0xf0 => return None,
+            _ => 2,
+        };
+
+        self.data.push(byte);
+        if self.data.len() < len {
+            return None;
+        }
+
+        let channel = self.status & 0x0f;
+        let (number, value) = (self.data[0], *self.data.last().unwrap());
+        self.data.clear();
+
+        match self.status & 0xf0 {
+            0xb0 => Some((Input::Cc { channel, number }, value)),
+            0x90 => Some((Input::Note { channel, number }, value)),
+            0x80 => Some((Input::Note { channel, number }, 0)),
+            _ => None,
+        }
+    }
+}
+
+/// Turns MIDI into controls, and learns new bindings on request.
+pub struct MidiMapper {
+    bindings: Bindings,
+    path: PathBuf,
+    controls: Sender<Control>,
+    learning: Option<(Target, Sender<Input>)>,
+    last_learned: Option<Input>,
+}
+
+impl MidiMapper {
+    fn handle(&mut self, input: Input, value: u8) {
+        if let Some((target, learned)) = self.learning.take() {
+            // Ignore note-offs and button releases, so the next press of
+            // anything gets bound. A knob keeps sending while it is still
+            // being turned, so it can't be bound twice in a row either.
+            if value > 0 && self.last_learned != Some(input) {
+                self.last_learned = Some(input);
+                self.bindings.bind(input, target);
+                if let Err(e) = self.bindings.save(&self.path) {
+                    eprintln!("midi: failed to save {}: {}", self.path.display(), e);
+                }
+                learned.send(input).ok();
+                return;
+            }
+            self.learning = Some((target, learned));
+        }
+
+        if let Some(control) = self.bindings.control(input, value) {
+            self.controls.send(control).ok();
+        }
+    }
+}
+
+/// A running MIDI input.
+pub struct Midi {
+    mapper: Arc<Mutex<MidiMapper>>,
+    #[cfg(feature = "midi")]
+    _connection: Option<midir::MidiInputConnection<()>>,
+}
+
+impl Midi {
+    /// Starts reading `source`, using and updating the bindings stored at
+    /// `map_path`.
+    pub fn start(
+        source: MidiSource,
+        map_path: &str,
+        controls: Sender<Control>,
+    ) -> io::Result<Midi> {
+        let mapper = Arc::new(Mutex::new(MidiMapper {
+            bindings: Bindings::load(map_path)?,
+            path: PathBuf::from(map_path),
+            controls,
+            learning: None,
+            last_learned: None,
+        }));
+        let shared = mapper.clone();
+
+        match source {
+            MidiSource::Raw(path) => {
+                let file = BufReader::new(File::open(&path)?);
+                thread::spawn(move || {
+                    let mut parser = Parser::default();
+                    for byte in file.bytes() {
+                        match byte {
+                            Ok(byte) => {
+                                if let Some((input, value)) = parser.push(byte) {
+                                    shared.lock().unwrap().handle(input, value);
+                                }
+                            }
+                            Err(e) => {
+                                eprintln!("midi: {}", e);
+                                return;
+                            }
+                        }
+                    }
+                });
+
+                Ok(Midi {
+                    mapper,
+                    #[cfg(feature = "midi")]
+                    _connection: None,
+                })
+            }
+            #[cfg(feature = "midi")]
+            source => {
+                let connection = connect(source, shared)?;
+                Ok(Midi {
+                    mapper,
+                    _connection: Some(connection),
+                })
+            }
+        }
+    }
+
+    /// Walks through `targets` on the terminal, binding each one to the next
+    /// control that is moved. Enter skips a target. Runs on its own thread,
+    /// so the generator keeps running meanwhile.
+    pub fn learn(&self, targets: Vec<Target>) {
+        let mapper = self.mapper.clone();
+
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            let (learned_tx, learned) = std::sync::mpsc::channel();
+            let (skip_tx, skip) = std::sync::mpsc::channel();
+
+            thread::spawn(move || {
+                for _ in stdin.lock().lines() {
+                    if skip_tx.send(()).is_err() {
+                        return;
+                    }
+                }
+            });
+
+            for target in targets {
+                println!(
+                    "learn: move a control for `{}`, or press Enter to skip",
+                    target
+                );
+                mapper.lock().unwrap().learning = Some((target.clone(), learned_tx.clone()));
+
+                loop {
+                    if let Ok(input) = learned.try_recv() {
+                        println!("learn: bound {:?} to `{}`", input, target);
+                        break;
+                    }
+                    if skip.try_recv().is_ok() {
+                        mapper.lock().unwrap().learning = None;
+                        break;
+                    }
+                    thread::sleep(std::time::Duration::from_millis(10));
+                }
+            }
+
+            println!("learn: done");
+        });
+    }
+}
+
+#[cfg(feature = "midi")]
+fn connect(
+    source: MidiSource,
+    mapper: Arc<Mutex<MidiMapper>>,
+) -> io::Result<midir::MidiInputConnection<()>> {
+    use midir::os::unix::VirtualInput;
+    use midir::MidiInput;
+
+    let error = |e: &dyn fmt::Display| io::Error::other(e.to_string());
+    let input = MidiInput::new("matryx_generator").map_err(|e| error(&e))?;
+
+    let callback = move |_: u64, message: &[u8], _: &mut ()| {
+        let mut parser = Parser::default();
+        for byte in message {
+            if let Some((input, value)) = parser.push(*byte) {
+                mapper.lock().unwrap().handle(input, value);
+            }
+        }
+    };
+
+    match source {
+        MidiSource::Virtual => input
+            .create_virtual("matryx_generator", callback, ())
+            .map_err(|e| error(&e)),
+        MidiSource::Port(name) => {
+            let port = input
+                .ports()
+                .into_iter()
+                .find(|port| {
+                    input
+                        .port_name(port)
+                        .is_ok_and(|port_name| port_name.contains(&name))
+                })
+                .ok_or_else(|| error(&format!("no MIDI port matching {:?}", name)))?;
+
+            input
+                .connect(&port, "matryx_generator", callback, ())
+                .map_err(|e| error(&e))
+        }
+        MidiSource::Raw(_) => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Vec<(Input, u8)> {
+        let mut parser = Parser::default();
+        bytes.iter().filter_map(|byte| parser.push(*byte)).collect()
+    }
+
+    #[test]
+    fn parses_running_status() {
+        let cc = |number, value| (Input::Cc { channel: 2, number }, value);
+        assert_eq!(
+            parse(&[0xb2, 21, 10, 22, 20, 21, 127]),
+            [cc(21, 10), cc(22, 20), cc(21, 127)]
+        );
+    }
+
+    #[test]
+    fn zero_velocity_note_on_is_note_off() {
+        let note = |value| {
+            (
+                Input::Note {
+                    channel: 9,
+                    number: 36,
+                },
+                value,
+            )
+        };
+        assert_eq!(
+            parse(&[0x99, 36, 100, 0x99, 36, 0, 0x89, 36, 64]),
+            [note(100), note(0), note(0)]
+        );
+    }
+
+    #[test]
+    fn ignores_realtime_bytes_inside_messages() {
+        assert_eq!(
+            parse(&[0xb0, 0xf8, 7, 0xfa, 99, 0xfe]),
+            [(
+                Input::Cc {
+                    channel: 0,
+                    number: 7
+                },
+                99
+            )]
+        );
+        // System exclusive is skipped until the next status byte.
+        assert_eq!(
+            parse(&[0xf0, 1, 2, 3, 0xf7, 0x90, 60, 1]),
+            [(
+                Input::Note {
+                    channel: 0,
+                    number: 60
+                },
+                1
+            )]
+        );
+    }
+
+    #[test]
+    fn bindings_survive_a_save() {
+        let text = "cc 0 21 param wave hue_speed # a knob\n\
+                    \n\
+                    cc 0 7 brightness\n\
+                    note 9 36 next\n\
+                    note 9 35 prev\n\
+                    note 9 37 scene plasma\n";
+        let bindings = Bindings::parse(text).unwrap();
+
+        let path = std::env::temp_dir().join(format!("matryx_midi_{}.map", std::process::id()));
+        bindings.save(&path).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            saved,
+            "cc 0 21 param wave hue_speed\n\
+             cc 0 7 brightness\n\
+             note 9 36 next\n\
+             note 9 35 prev\n\
+             note 9 37 scene plasma\n"
+        );
+        assert_eq!(
+            Bindings::parse(&saved).unwrap().control(
+                Input::Cc {
+                    channel: 0,
+                    number: 7
+                },
+                127
+            ),
+            Some(Control::Brightness(1.0))
+        );
+        assert!(Bindings::parse("cc 0 300 next").is_err());
+        assert!(Bindings::parse("pad 0 1 next").is_err());
+        assert!(Bindings::parse("note 0 1 jump").is_err());
+    }
+}
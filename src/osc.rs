@@ -0,0 +1,392 @@
+use std::convert::{TryFrom, TryInto};
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use crate::control::{Control, Disturbance, Event};
+
+/// An OSC argument. Only the types that can sensibly drive the generator
+/// are kept, everything else is skipped.
+#[derive(Clone, Debug, PartialEq)]
+enum Arg {
+    Float(f32),
+    Str(String),
+}
+
+impl Arg {
+    /// The argument as a number, if it is a finite one.
+    fn as_f32(&self) -> Option<f32> {
+        let value = match self {
+            Arg::Float(value) => Some(*value),
+            Arg::Str(s) => s.parse().ok(),
+        };
+        value.filter(|v: &f32| v.is_finite())
+    }
+}
+
+/// Listens for OSC messages over UDP and turns them into [`Control`]s.
+///
+/// Understood addresses:
+///
+/// * `/scene/next`, `/scene/prev`: switch scenes. Buttons that also send a
+///   release with `0` are fine, only non-zero values switch.
+/// * `/scene/select <name>` or `/scene/<name>`: switch to a scene by name.
+/// * `/param/<scene>/<name> <value>`: set a parameter of a scene.
+/// * `/param/<name> <value>`: set a parameter of the current scene.
+/// * `/brightness <value>`: set the output brightness.
+/// * `/gravity <x> <y>`: set gravity for scenes that simulate it, in g with
+///   y going down the canvas, as an accelerometer would send it.
+/// * `/disturb <x> <y> [strength]`: drop energy into the current scene at a
+///   point, with `x` and `y` across and down the canvas.
+/// * `/disturb/line <x0> <y0> <x1> <y1> [strength]`: the same along a line.
+///   `strength` is `1.0` when left out.
+///
+/// Other values are expected in `0.0..=1.0`, as most OSC surfaces send, and
+/// are mapped onto each parameter's own range.
+pub fn start<A: ToSocketAddrs>(addr: A, controls: Sender<Control>) -> io::Result<()> {
+    let socket = UdpSocket::bind(addr)?;
+
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+
+        loop {
+            let len = match socket.recv(&mut buf) {
+                Ok(len) => len,
+                Err(e) => {
+                    eprintln!("osc: {}", e);
+                    continue;
+                }
+            };
+
+            let mut messages = Vec::new();
+            if decode_packet(&buf[..len], &mut messages).is_none() {
+                eprintln!("osc: dropped a malformed packet");
+                continue;
+            }
+
+            for (address, args) in messages {
+                match to_control(&address, &args) {
+                    Some(control) => {
+                        if controls.send(control).is_err() {
+                            return;
+                        }
+                    }
+                    None => eprintln!("osc: ignored {} {:?}", address, args),
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn to_control(address: &str, args: &[Arg]) -> Option<Control> {
+    let parts: Vec<&str> = address.trim_start_matches('/').split('/').collect();
+    let value = args.first().and_then(Arg::as_f32);
+    let pressed = value.is_none_or(|value| value != 0.0);
+
+    match parts.as_slice() {
+        ["scene", "next"] if pressed => Some(Control::NextScene),
+        ["scene", "prev"] if pressed => Some(Control::PrevScene),
+        ["scene", "next"] | ["scene", "prev"] => None,
+        ["scene", "select"] => match args.first()? {
+            Arg::Str(name) => Some(Control::SelectScene(name.clone())),
+            Arg::Float(_) => None,
+        },
+        ["scene", name] if pressed => Some(Control::SelectScene(name.to_string())),
+        ["param", scene, name] => Some(Control::SetParam {
+            scene: Some(scene.to_string()),
+            name: name.to_string(),
+            value: value?,
+        }),
+        ["param", name] => Some(Control::SetParam {
+            scene: None,
+            name: name.to_string(),
+            value: value?,
+        }),
+        ["brightness"] => Some(Control::Brightness(value?)),
+        ["gravity"] => Some(Control::Gravity {
+            x: value?,
+            y: args.get(1)?.as_f32()?,
+        }),
+        ["disturb"] => Some(Control::Disturb(Disturbance::Point {
+            x: value?,
+            y: args.get(1)?.as_f32()?,
+            strength: args.get(2).map_or(Some(1.0), Arg::as_f32)?,
+        })),
+        ["disturb", "line"] => {
+            let coord = |i: usize| args.get(i).and_then(Arg::as_f32);
+            Some(Control::Disturb(Disturbance::Line {
+                from: (coord(0)?, coord(1)?),
+                to: (coord(2)?, coord(3)?),
+                strength: args.get(4).map_or(Some(1.0), Arg::as_f32)?,
+            }))
+        }
+        ["event", name] => Some(Control::Event(Event {
+            name: name.to_string(),
+            value,
+        })),
+        _ => None,
+    }
+}
+
+fn decode_packet(packet: &[u8], messages: &mut Vec<(String, Vec<Arg>)>) -> Option<()> {
+    if let Some(mut rest) = packet.strip_prefix(b"#bundle\0") {
+        // Skip the time tag, bundles are handled as soon as they arrive.
+        rest = rest.get(8..)?;
+
+        while !rest.is_empty() {
+            let size = usize::try_from(i32::from_be_bytes(rest.get(..4)?.try_into().ok()?)).ok()?;
+            let end = size.checked_add(4)?;
+            decode_packet(rest.get(4..end)?, messages)?;
+            rest = &rest[end..];
+        }
+
+        return Some(());
+    }
+
+    let mut reader = Reader { data: packet };
+    let address = reader.string()?;
+    let mut args = Vec::new();
+
+    // Very old senders leave out the type tags, treat that as no arguments.
+    let tags = if reader.data.first() == Some(&b',') {
+        reader.string()?
+    } else {
+        String::new()
+    };
+
+    for tag in tags.chars().skip(1) {
+        match tag {
+            'f' => args.push(Arg::Float(f32::from_be_bytes(reader.bytes4()?))),
+            'i' => args.push(Arg::Float(i32::from_be_bytes(reader.bytes4()?) as f32)),
+            'd' => args.push(Arg::Float(f64::from_be_bytes(reader.bytes8()?) as f32)),
+            'h' => args.push(Arg::Float(i64::from_be_bytes(reader.bytes8()?) as f32)),
+            's' | 'S' => args.push(Arg::Str(reader.string()?)),
+            'T' => args.push(Arg::Float(1.0)),
+            'F' => args.push(Arg::Float(0.0)),
+            'N' | 'I' => {}
+            'c' | 'r' | 'm' => {
+                reader.bytes4()?;
+            }
+            't' => {
+                reader.bytes8()?;
+            }
+            'b' => {
+                let size = usize::try_from(i32::from_be_bytes(reader.bytes4()?)).ok()?;
+                reader.skip(padded(size)?)?;
+            }
+            _ => return None,
+        }
+    }
+
+    messages.push((address, args));
+    Some(())
+}
+
+fn padded(len: usize) -> Option<usize> {
+    Some(len.checked_add(3)? & !3)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn skip(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(..len)?;
+        self.data = &self.data[len..];
+        Some(bytes)
+    }
+
+    fn bytes4(&mut self) -> Option<[u8; 4]> {
+        self.skip(4)?.try_into().ok()
+    }
+
+    fn bytes8(&mut self) -> Option<[u8; 8]> {
+        self.skip(8)?.try_into().ok()
+    }
+
+    /// Reads a null-terminated string, padded to four bytes.
+    fn string(&mut self) -> Option<String> {
+        let len = self.data.iter().position(|b| *b == 0)?;
+        let s = String::from_utf8(self.data[..len].to_vec()).ok()?;
+        self.skip(padded(len + 1)?.min(self.data.len()))?;
+        Some(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::Param;
+
+    /// Encodes a string the way OSC does, null-terminated and padded.
+    fn string(s: &str) -> Vec<u8> {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.push(0);
+        while !bytes.len().is_multiple_of(4) {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    fn message(address: &str, values: &[f32]) -> Vec<u8> {
+        let mut packet = string(address);
+        packet.extend(string(&format!(",{}", "f".repeat(values.len()))));
+        for value in values {
+            packet.extend(value.to_be_bytes());
+        }
+        packet
+    }
+
+    fn bundle(elements: &[Vec<u8>]) -> Vec<u8> {
+        let mut packet = b"#bundle\0".to_vec();
+        packet.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+        for element in elements {
+            packet.extend((element.len() as i32).to_be_bytes());
+            packet.extend(element);
+        }
+        packet
+    }
+
+    fn decode(packet: &[u8]) -> Option<Vec<(String, Vec<Arg>)>> {
+        let mut messages = Vec::new();
+        decode_packet(packet, &mut messages)?;
+        Some(messages)
+    }
+
+    #[test]
+    fn decodes_messages() {
+        let mut packet = string("/mixed");
+        packet.extend(string(",sbiT"));
+        packet.extend(string("fire"));
+        packet.extend(5i32.to_be_bytes());
+        packet.extend([1, 2, 3, 4, 5, 0, 0, 0]);
+        packet.extend(7i32.to_be_bytes());
+
+        assert_eq!(
+            decode(&message("/brightness", &[0.5])).unwrap(),
+            [("/brightness".to_string(), vec![Arg::Float(0.5)])]
+        );
+        assert_eq!(
+            decode(&packet).unwrap(),
+            [(
+                "/mixed".to_string(),
+                vec![
+                    Arg::Str("fire".to_string()),
+                    Arg::Float(7.0),
+                    Arg::Float(1.0)
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn decodes_nested_bundles() {
+        let inner = bundle(&[message("/b", &[2.0]), message("/c", &[])]);
+        let packet = bundle(&[message("/a", &[1.0]), inner]);
+
+        let addresses: Vec<String> = decode(&packet)
+            .unwrap()
+            .into_iter()
+            .map(|(address, _)| address)
+            .collect();
+        assert_eq!(addresses, ["/a", "/b", "/c"]);
+    }
+
+    #[test]
+    fn drops_truncated_and_negative_sizes() {
+        let whole = message("/brightness", &[0.5]);
+        assert!(decode(&whole[..whole.len() - 1]).is_none());
+
+        let mut truncated = bundle(std::slice::from_ref(&whole));
+        truncated.pop();
+        assert!(decode(&truncated).is_none());
+
+        let mut negative = bundle(&[]);
+        negative.extend((-1i32).to_be_bytes());
+        negative.extend(&whole);
+        assert!(decode(&negative).is_none());
+
+        let mut huge = bundle(&[]);
+        huge.extend(i32::MAX.to_be_bytes());
+        assert!(decode(&huge).is_none());
+
+        let mut blob = string("/blob");
+        blob.extend(string(",b"));
+        blob.extend((-4i32).to_be_bytes());
+        assert!(decode(&blob).is_none());
+    }
+
+    #[test]
+    fn maps_addresses_to_controls() {
+        let f = |value: f32| vec![Arg::Float(value)];
+
+        assert_eq!(to_control("/scene/next", &[]), Some(Control::NextScene));
+        assert_eq!(to_control("/scene/next", &f(0.0)), None);
+        assert_eq!(
+            to_control("/scene/select", &[Arg::Str("sand".to_string())]),
+            Some(Control::SelectScene("sand".to_string()))
+        );
+        assert_eq!(
+            to_control("/scene/wave", &f(1.0)),
+            Some(Control::SelectScene("wave".to_string()))
+        );
+        assert_eq!(
+            to_control("/param/wave/speed", &f(0.25)),
+            Some(Control::SetParam {
+                scene: Some("wave".to_string()),
+                name: "speed".to_string(),
+                value: 0.25,
+            })
+        );
+        assert_eq!(
+            to_control("/gravity", &[Arg::Float(0.5), Arg::Str("-1".to_string())]),
+            Some(Control::Gravity { x: 0.5, y: -1.0 })
+        );
+        assert_eq!(
+            to_control("/disturb", &[Arg::Float(0.1), Arg::Float(0.2)]),
+            Some(Control::Disturb(Disturbance::Point {
+                x: 0.1,
+                y: 0.2,
+                strength: 1.0,
+            }))
+        );
+        assert_eq!(to_control("/brightness", &[]), None);
+        assert_eq!(to_control("/nonsense", &f(1.0)), None);
+    }
+
+    #[test]
+    fn drops_non_finite_values() {
+        let f = |value: f32| vec![Arg::Float(value)];
+
+        assert_eq!(to_control("/param/wave/decay", &f(f32::NAN)), None);
+        assert_eq!(to_control("/param/threshold", &f(f32::INFINITY)), None);
+        assert_eq!(
+            to_control("/param/wave/decay", &[Arg::Str("nan".to_string())]),
+            None
+        );
+        assert_eq!(to_control("/brightness", &f(f32::NEG_INFINITY)), None);
+        assert_eq!(
+            to_control("/gravity", &[Arg::Float(0.0), Arg::Float(f32::NAN)]),
+            None
+        );
+    }
+
+    #[test]
+    fn maps_values_onto_param_ranges() {
+        let param = Param::new("speed", 2.0, 10.0, 4.0);
+        let value = |arg: f32| match to_control("/param/speed", &[Arg::Float(arg)]) {
+            Some(Control::SetParam { value, .. }) => param.denormalize(value),
+            other => panic!("unexpected {:?}", other),
+        };
+
+        assert_eq!(value(0.0), 2.0);
+        assert_eq!(value(0.5), 6.0);
+        assert_eq!(value(1.0), 10.0);
+        assert_eq!(value(-1.0), 2.0);
+        assert_eq!(value(2.0), 10.0);
+    }
+}
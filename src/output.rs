@@ -0,0 +1,84 @@
+use std::sync::Barrier;
+use std::thread;
+
+use led_matrix_zmq::client::{MatrixClient, MatrixClientSettings};
+
+use crate::mapping::{Display, PixelMap};
+use crate::Canvas;
+
+struct Target {
+    client: MatrixClient,
+    pixel_map: PixelMap,
+    frame: Vec<u8>,
+}
+
+/// Sends the canvas to every matrix server of a [`Display`].
+///
+/// Every server gets the same frame: all regions are mapped before any is
+/// sent, the sends start together, and the next frame isn't sent until every
+/// server has taken this one, so no server runs ahead of the others.
+pub struct Output {
+    targets: Vec<Target>,
+    brightness: f32,
+}
+
+impl Output {
+    pub fn new(display: &Display) -> Self {
+        Output {
+            targets: targets(display),
+            brightness: 1.0,
+        }
+    }
+
+    /// Sends to `display` from now on, as when the layout has changed.
+    pub fn set_display(&mut self, display: &Display) {
+        self.targets = targets(display);
+    }
+
+    /// Scales every pixel sent from now on, from `0.0` (off) to `1.0`.
+    pub fn set_brightness(&mut self, brightness: f32) {
+        self.brightness = brightness.clamp(0.0, 1.0);
+    }
+
+    pub fn send(&mut self, canvas: &Canvas) {
+        for target in self.targets.iter_mut() {
+            target.pixel_map.apply(canvas.pixels(), &mut target.frame);
+
+            if self.brightness < 1.0 {
+                for value in target.frame.iter_mut() {
+                    *value = (*value as f32 * self.brightness) as u8;
+                }
+            }
+        }
+
+        match self.targets.as_mut_slice() {
+            [target] => target.client.send_frame(&target.frame),
+            targets => {
+                let start = Barrier::new(targets.len());
+                thread::scope(|scope| {
+                    for target in targets.iter_mut() {
+                        let start = &start;
+                        scope.spawn(move || {
+                            start.wait();
+                            target.client.send_frame(&target.frame);
+                        });
+                    }
+                });
+            }
+        }
+    }
+}
+
+fn targets(display: &Display) -> Vec<Target> {
+    display
+        .screens
+        .iter()
+        .map(|screen| Target {
+            client: MatrixClient::new(MatrixClientSettings {
+                addr: screen.addr.clone(),
+            }),
+            pixel_map: PixelMap::from_screen(screen, display.canvas_width),
+            frame: Vec::new(),
+        })
+        .collect()
+}
@@ -0,0 +1,55 @@
+use crate::gradient::Palette;
+
+/// A tweakable scene parameter, with its current value and range.
+#[derive(Copy, Clone, Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub value: f32,
+}
+
+impl Param {
+    pub fn new(name: &'static str, min: f32, max: f32, value: f32) -> Self {
+        Param {
+            name,
+            min,
+            max,
+            value,
+        }
+    }
+
+    /// Maps `0.0..=1.0` onto this parameter's range.
+    pub fn denormalize(&self, value: f32) -> f32 {
+        self.min + value.clamp(0.0, 1.0) * (self.max - self.min)
+    }
+}
+
+/// Names of general-purpose `0.0..=1.0` parameters, for scenes whose
+/// behavior is defined in a file and can use them however it likes.
+pub const KNOB_NAMES: [&str; 4] = ["p1", "p2", "p3", "p4"];
+
+/// Knob parameters with their current `values`.
+pub fn knob_params(values: &[f32; 4]) -> Vec<Param> {
+    KNOB_NAMES
+        .iter()
+        .zip(values)
+        .map(|(name, value)| Param::new(name, 0.0, 1.0, *value))
+        .collect()
+}
+
+/// A `palette` parameter choosing between a scene's own coloring, at 0, and
+/// the built-in palettes in [`Palette::NAMES`] order.
+pub fn palette_param(palette: usize) -> Param {
+    Param::new("palette", 0.0, Palette::NAMES.len() as f32, palette as f32)
+}
+
+/// Resolves a value of [`palette_param`].
+pub fn palette_from_param(value: f32) -> (usize, Option<Palette>) {
+    let index = (value.round().max(0.0) as usize).min(Palette::NAMES.len());
+
+    match index {
+        0 => (0, None),
+        i => (i, Palette::named(Palette::NAMES[i - 1])),
+    }
+}
@@ -0,0 +1,579 @@
+use std::f32::consts::{PI, TAU};
+use std::io;
+
+/// One step of a compiled [`Program`], working on a stack of values.
+#[derive(Copy, Clone, Debug)]
+enum Op {
+    Const(f32),
+    Load(usize),
+    Store(usize),
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    Call1(fn(f32) -> f32),
+    Call2(fn(f32, f32) -> f32),
+    Call3(fn(f32, f32, f32) -> f32),
+}
+
+impl Op {
+    /// How many values the op takes off the stack.
+    fn arity(self) -> usize {
+        match self {
+            Op::Const(_) | Op::Load(_) => 0,
+            Op::Store(_) | Op::Neg | Op::Call1(_) => 1,
+            Op::Call3(_) => 3,
+            _ => 2,
+        }
+    }
+
+    /// Applies an op that computes something to its `args`.
+    fn apply(self, args: &[f32]) -> f32 {
+        let truth = |b: bool| if b { 1.0 } else { 0.0 };
+
+        match self {
+            Op::Neg => -args[0],
+            Op::Add => args[0] + args[1],
+            Op::Sub => args[0] - args[1],
+            Op::Mul => args[0] * args[1],
+            Op::Div => args[0] / args[1],
+            Op::Rem => args[0].rem_euclid(args[1]),
+            Op::Pow => args[0].powf(args[1]),
+            Op::Lt => truth(args[0] < args[1]),
+            Op::Gt => truth(args[0] > args[1]),
+            Op::Le => truth(args[0] <= args[1]),
+            Op::Ge => truth(args[0] >= args[1]),
+            Op::Eq => truth(args[0] == args[1]),
+            Op::Ne => truth(args[0] != args[1]),
+            Op::Call1(f) => f(args[0]),
+            Op::Call2(f) => f(args[0], args[1]),
+            Op::Call3(f) => f(args[0], args[1], args[2]),
+            Op::Const(_) | Op::Load(_) | Op::Store(_) => unreachable!(),
+        }
+    }
+}
+
+/// Looks up a math function by name.
+fn function(name: &str) -> Option<Op> {
+    let op = match name {
+        "sin" => Op::Call1(f32::sin),
+        "cos" => Op::Call1(f32::cos),
+        "tan" => Op::Call1(f32::tan),
+        "asin" => Op::Call1(f32::asin),
+        "acos" => Op::Call1(f32::acos),
+        "atan" => Op::Call1(f32::atan),
+        "sqrt" => Op::Call1(f32::sqrt),
+        "abs" => Op::Call1(f32::abs),
+        "floor" => Op::Call1(f32::floor),
+        "ceil" => Op::Call1(f32::ceil),
+        "round" => Op::Call1(f32::round),
+        "fract" => Op::Call1(|x| x - x.floor()),
+        "sign" => Op::Call1(|x| if x == 0.0 { 0.0 } else { x.signum() }),
+        "exp" => Op::Call1(f32::exp),
+        "ln" => Op::Call1(f32::ln),
+        "atan2" => Op::Call2(f32::atan2),
+        "pow" => Op::Call2(f32::powf),
+        "min" => Op::Call2(f32::min),
+        "max" => Op::Call2(f32::max),
+        "hypot" => Op::Call2(f32::hypot),
+        "step" => Op::Call2(|edge, x| if x < edge { 0.0 } else { 1.0 }),
+        "clamp" => Op::Call3(|x, low, high| x.max(low).min(high)),
+        "mix" => Op::Call3(|a, b, t| a + (b - a) * t),
+        "smoothstep" => Op::Call3(|edge0, edge1, x| {
+            let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        }),
+        "if" => Op::Call3(|condition, a, b| if condition != 0.0 { a } else { b }),
+        _ => return None,
+    };
+
+    Some(op)
+}
+
+fn constant(name: &str) -> Option<f32> {
+    match name {
+        "pi" => Some(PI),
+        "tau" => Some(TAU),
+        _ => None,
+    }
+}
+
+/// A parsed expression, before it's flattened into ops.
+enum Node {
+    Const(f32),
+    Load(usize),
+    Apply(Op, Vec<Node>),
+}
+
+impl Node {
+    /// Applies `op` to `args`, working it out right away when they're all
+    /// constant.
+    fn apply(op: Op, args: Vec<Node>) -> Node {
+        let values: Option<Vec<f32>> = args
+            .iter()
+            .map(|arg| match arg {
+                Node::Const(value) => Some(*value),
+                _ => None,
+            })
+            .collect();
+
+        match values {
+            Some(values) => Node::Const(op.apply(&values)),
+            None => Node::Apply(op, args),
+        }
+    }
+
+    fn emit(self, ops: &mut Vec<Op>) {
+        match self {
+            Node::Const(value) => ops.push(Op::Const(value)),
+            Node::Load(slot) => ops.push(Op::Load(slot)),
+            Node::Apply(op, args) => {
+                for arg in args {
+                    arg.emit(ops);
+                }
+                ops.push(op);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    Name(String),
+    Symbol(&'static str),
+    End,
+}
+
+const SYMBOLS: [&str; 16] = [
+    "<=", ">=", "==", "!=", "<", ">", "+", "-", "*", "/", "%", "^", "(", ")", ",", "=",
+];
+
+/// Splits `source` into tokens, each with the column it starts at.
+fn tokenize(source: &str) -> io::Result<Vec<(usize, Token)>> {
+    let mut tokens = vec![];
+    let mut rest = source.trim_start();
+
+    while !rest.is_empty() {
+        let column = source.len() - rest.len() + 1;
+        let c = rest.chars().next().unwrap();
+
+        let length = if c.is_ascii_digit() || c == '.' {
+            let length = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number = rest[..length].parse().map_err(|_| {
+                invalid(format!(
+                    "column {}: bad number {:?}",
+                    column,
+                    &rest[..length]
+                ))
+            })?;
+            tokens.push((column, Token::Number(number)));
+            length
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let length = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push((column, Token::Name(rest[..length].to_string())));
+            length
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(*symbol))
+                .ok_or_else(|| invalid(format!("column {}: unexpected {:?}", column, c)))?;
+            tokens.push((column, Token::Symbol(symbol)));
+            symbol.len()
+        };
+
+        rest = rest[length..].trim_start();
+    }
+
+    tokens.push((source.len() + 1, Token::End));
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    /// Names of the variables defined so far, by slot.
+    names: &'a [String],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].1
+    }
+
+    fn advance(&mut self) {
+        if *self.peek() != Token::End {
+            self.next += 1;
+        }
+    }
+
+    /// Moves past `symbol` if it's next.
+    fn eat(&mut self, symbol: &'static str) -> bool {
+        if *self.peek() == Token::Symbol(symbol) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> io::Result<()> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{}`", symbol)))
+        }
+    }
+
+    /// An error pointing at the next token.
+    fn error(&self, message: &str) -> io::Error {
+        invalid(format!("column {}: {}", self.tokens[self.next].0, message))
+    }
+
+    fn unexpected(&self, expected: &str) -> io::Error {
+        let found = match self.peek() {
+            Token::Number(number) => format!("{}", number),
+            Token::Name(name) => format!("`{}`", name),
+            Token::Symbol(symbol) => format!("`{}`", symbol),
+            Token::End => "the end".to_string(),
+        };
+        self.error(&format!("expected {}, found {}", expected, found))
+    }
+
+    /// Parses all of the tokens as one expression.
+    fn parse(mut self) -> io::Result<Node> {
+        let node = self.comparison()?;
+        if *self.peek() != Token::End {
+            return Err(self.unexpected("an operator"));
+        }
+        Ok(node)
+    }
+
+    fn comparison(&mut self) -> io::Result<Node> {
+        let mut node = self.sum()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol("<") => Op::Lt,
+                Token::Symbol(">") => Op::Gt,
+                Token::Symbol("<=") => Op::Le,
+                Token::Symbol(">=") => Op::Ge,
+                Token::Symbol("==") => Op::Eq,
+                Token::Symbol("!=") => Op::Ne,
+                _ => return Ok(node),
+            };
+            self.advance();
+            node = Node::apply(op, vec![node, self.sum()?]);
+        }
+    }
+
+    fn sum(&mut self) -> io::Result<Node> {
+        let mut node = self.product()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol("+") => Op::Add,
+                Token::Symbol("-") => Op::Sub,
+                _ => return Ok(node),
+            };
+            self.advance();
+            node = Node::apply(op, vec![node, self.product()?]);
+        }
+    }
+
+    fn product(&mut self) -> io::Result<Node> {
+        let mut node = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol("*") => Op::Mul,
+                Token::Symbol("/") => Op::Div,
+                Token::Symbol("%") => Op::Rem,
+                _ => return Ok(node),
+            };
+            self.advance();
+            node = Node::apply(op, vec![node, self.unary()?]);
+        }
+    }
+
+    fn unary(&mut self) -> io::Result<Node> {
+        if self.eat("-") {
+            Ok(Node::apply(Op::Neg, vec![self.unary()?]))
+        } else if self.eat("+") {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    /// `^` binds tighter than a leading minus and groups to the right, so
+    /// `-2^2` is -4 and `2^3^2` is 512.
+    fn power(&mut self) -> io::Result<Node> {
+        let base = self.primary()?;
+        if self.eat("^") {
+            Ok(Node::apply(Op::Pow, vec![base, self.unary()?]))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn primary(&mut self) -> io::Result<Node> {
+        match self.peek().clone() {
+            Token::Number(number) => {
+                self.advance();
+                Ok(Node::Const(number))
+            }
+            Token::Symbol("(") => {
+                self.advance();
+                let node = self.comparison()?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Token::Name(name) if self.tokens[self.next + 1].1 == Token::Symbol("(") => {
+                let op = function(&name)
+                    .ok_or_else(|| self.error(&format!("no function called `{}`", name)))?;
+                self.advance();
+                self.advance();
+
+                let mut args = vec![];
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.comparison()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+
+                if args.len() != op.arity() {
+                    return Err(invalid(format!(
+                        "`{}` takes {} arguments, got {}",
+                        name,
+                        op.arity(),
+                        args.len()
+                    )));
+                }
+                Ok(Node::apply(op, args))
+            }
+            Token::Name(name) => {
+                let node = match self.names.iter().position(|known| *known == name) {
+                    Some(slot) => Node::Load(slot),
+                    None => Node::Const(
+                        constant(&name)
+                            .ok_or_else(|| self.error(&format!("no variable called `{}`", name)))?,
+                    ),
+                };
+                self.advance();
+                Ok(node)
+            }
+            _ => Err(self.unexpected("a value")),
+        }
+    }
+}
+
+/// Compiles assignments one at a time into a [`Program`].
+pub struct Compiler {
+    /// Variable names, by slot. Built-ins come first.
+    names: Vec<String>,
+    builtins: usize,
+    ops: Vec<Op>,
+}
+
+impl Compiler {
+    /// A compiler whose programs can read `builtins`, which are filled in
+    /// before each run in the same order.
+    pub fn new(builtins: &[&str]) -> Self {
+        Compiler {
+            names: builtins.iter().map(|name| name.to_string()).collect(),
+            builtins: builtins.len(),
+            ops: vec![],
+        }
+    }
+
+    /// Adds a `name = expression` statement. Later statements can use
+    /// `name`, and assigning it again replaces its value from then on.
+    pub fn assign(&mut self, statement: &str) -> io::Result<()> {
+        let mut parser = Parser {
+            tokens: tokenize(statement)?,
+            next: 0,
+            names: &self.names,
+        };
+
+        let name = match parser.peek() {
+            Token::Name(name) => name.clone(),
+            _ => return Err(parser.unexpected("a name")),
+        };
+        if self.names[..self.builtins].contains(&name) || constant(&name).is_some() {
+            return Err(parser.error(&format!("`{}` is built in and can't be assigned", name)));
+        }
+        parser.advance();
+        parser.expect("=")?;
+
+        parser.parse()?.emit(&mut self.ops);
+
+        let slot = match self.names.iter().position(|known| *known == name) {
+            Some(slot) => slot,
+            None => {
+                self.names.push(name);
+                self.names.len() - 1
+            }
+        };
+        self.ops.push(Op::Store(slot));
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> Program {
+        Program {
+            names: self.names,
+            ops: self.ops,
+        }
+    }
+}
+
+/// Compiled assignments, run once per pixel.
+pub struct Program {
+    names: Vec<String>,
+    ops: Vec<Op>,
+}
+
+impl Program {
+    /// The slot `name` is kept in, if it's a variable.
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|known| known == name)
+    }
+
+    /// How many values `run` needs in `vars`.
+    pub fn slots(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Runs the assignments, reading and writing variables in `vars`.
+    /// `stack` is only passed in so its memory is reused between runs.
+    pub fn run(&self, vars: &mut [f32], stack: &mut Vec<f32>) {
+        stack.clear();
+
+        for &op in &self.ops {
+            match op {
+                Op::Const(value) => stack.push(value),
+                Op::Load(slot) => stack.push(vars[slot]),
+                Op::Store(slot) => vars[slot] = stack.pop().unwrap(),
+                Op::Call1(f) => {
+                    let value = stack.last_mut().unwrap();
+                    *value = f(*value);
+                }
+                op => {
+                    let at = stack.len() - op.arity();
+                    let value = op.apply(&stack[at..]);
+                    stack.truncate(at);
+                    stack.push(value);
+                }
+            }
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str, x: f32) -> io::Result<f32> {
+        let mut compiler = Compiler::new(&["x"]);
+        compiler.assign(&format!("out = {}", source))?;
+        let program = compiler.finish();
+
+        let mut vars = vec![x, 0.0];
+        program.run(&mut vars, &mut vec![]);
+        Ok(vars[program.slot("out").unwrap()])
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3", 0.0).unwrap(), 7.0);
+        assert_eq!(eval("(1 + 2) * 3", 0.0).unwrap(), 9.0);
+        assert_eq!(eval("-2^2", 0.0).unwrap(), -4.0);
+        assert_eq!(eval("2^3^2", 0.0).unwrap(), 512.0);
+        assert_eq!(eval("2^-1", 0.0).unwrap(), 0.5);
+        assert_eq!(eval("1 + 1 < 3", 0.0).unwrap(), 1.0);
+        assert_eq!(eval("-7 % 3", 0.0).unwrap(), 2.0);
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        assert_eq!(eval("x * 2 + 1", 3.0).unwrap(), 7.0);
+        assert_eq!(eval("clamp(x, 0, 1)", 3.0).unwrap(), 1.0);
+        assert_eq!(eval("if(x > 2, x, -x)", 3.0).unwrap(), 3.0);
+        assert_eq!(eval("max(x, 5)", 3.0).unwrap(), 5.0);
+        assert!((eval("sin(pi / 2)", 0.0).unwrap() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn constants_are_folded() {
+        let mut compiler = Compiler::new(&["x"]);
+        compiler.assign("out = x * (2 * pi / 4 + cos(0))").unwrap();
+        assert_eq!(compiler.finish().ops.len(), 4);
+    }
+
+    #[test]
+    fn locals() {
+        let mut compiler = Compiler::new(&["x"]);
+        compiler.assign("a = x + 1").unwrap();
+        compiler.assign("a = a * 2").unwrap();
+        compiler.assign("out = a - x").unwrap();
+        let program = compiler.finish();
+
+        let mut vars = vec![3.0; program.slots()];
+        program.run(&mut vars, &mut vec![]);
+        assert_eq!(vars[program.slot("out").unwrap()], 5.0);
+    }
+
+    #[test]
+    fn errors() {
+        let message = |statement| {
+            let mut compiler = Compiler::new(&["x"]);
+            compiler.assign(statement).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            message("a = 1 +"),
+            "column 8: expected a value, found the end"
+        );
+        assert_eq!(
+            message("a = (1 + 2"),
+            "column 11: expected `)`, found the end"
+        );
+        assert_eq!(
+            message("a = 1 2"),
+            "column 7: expected an operator, found 2"
+        );
+        assert_eq!(message("a = y"), "column 5: no variable called `y`");
+        assert_eq!(message("a = foo(1)"), "column 5: no function called `foo`");
+        assert_eq!(message("a = min(1)"), "`min` takes 2 arguments, got 1");
+        assert_eq!(message("a = 1 $ 2"), "column 7: unexpected '$'");
+        assert_eq!(message("a + 1"), "column 3: expected `=`, found `+`");
+        assert_eq!(message("2 = 1"), "column 1: expected a name, found 2");
+        assert_eq!(
+            message("x = 1"),
+            "column 1: `x` is built in and can't be assigned"
+        );
+        assert_eq!(
+            message("pi = 1"),
+            "column 1: `pi` is built in and can't be assigned"
+        );
+    }
+}
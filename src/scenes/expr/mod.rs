@@ -0,0 +1,279 @@
+mod lang;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::audio::BANDS;
+use crate::gradient::Palette;
+use crate::params::{knob_params, palette_from_param, palette_param, Param, KNOB_NAMES};
+use crate::watch::FileWatch;
+use crate::{Canvas, FrameTick, Scene};
+use lang::{Compiler, Program};
+
+/// Slots of the per-pixel variables, which come first in [`BUILTINS`].
+const X: usize = 0;
+const Y: usize = 1;
+const U: usize = 2;
+const V: usize = 3;
+const PX: usize = 4;
+const PY: usize = 5;
+
+/// Variables every formula can read, in slot order.
+const BUILTINS: [&str; 17 + 2 * BANDS] = [
+    "x", "y", "u", "v", "px", "py", "w", "h", "t", "dt", "level", "beat", "onset", "p1", "p2",
+    "p3", "p4", "band0", "band1", "band2", "band3", "band4", "band5", "band6", "band7", "env0",
+    "env1", "env2", "env3", "env4", "env5", "env6", "env7",
+];
+
+/// What a formula's pixels are colored by.
+enum Output {
+    /// Slots of the red, green and blue values.
+    Rgb([usize; 3]),
+    /// Slot of a value looked up in a palette.
+    Value(usize),
+}
+
+/// A compiled formula file.
+pub struct Formula {
+    program: Program,
+    output: Output,
+    palette: Option<Palette>,
+}
+
+impl Formula {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Formula> {
+        Formula::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses a formula file. Each line assigns an expression to a name,
+    /// and the pixel's color comes from `r`, `g` and `b`, or else from
+    /// `value` looked up in a palette. A `palette` line picks the palette,
+    /// by name or file, and `#` begins a comment.
+    ///
+    /// ```text
+    /// palette fire
+    /// d = hypot(u, v)
+    /// value = sin(d * 20 - t * 3) * 0.5 + 0.5
+    /// ```
+    ///
+    /// Expressions can use `+ - * / % ^`, comparisons that give 1 or 0,
+    /// `pi`, `tau`, the names assigned on earlier lines, the functions
+    /// `sin cos tan asin acos atan atan2 sqrt abs floor ceil round fract
+    /// sign exp ln pow min max hypot step clamp mix smoothstep` and
+    /// `if(condition, then, else)`, and these variables:
+    ///
+    /// - `x`, `y`: position across the canvas, `0.0..=1.0`
+    /// - `u`, `v`: position from the center, scaled so the shorter side is
+    ///   `1.0` long
+    /// - `px`, `py`, `w`, `h`: position and canvas size in pixels
+    /// - `t`, `dt`: seconds since start and since the last frame
+    /// - `level`, `beat`, `onset`, `band0`..`band7`, `env0`..`env7`: audio
+    ///   analysis, see [`crate::audio::AudioFrame`]
+    /// - `p1`..`p4`: the scene's parameters
+    pub fn parse(text: &str) -> io::Result<Formula> {
+        let mut compiler = Compiler::new(&BUILTINS);
+        let mut palette = None;
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let at_line = |e: io::Error| invalid(format!("line {}: {}", n + 1, e));
+
+            if line.trim().is_empty() {
+                continue;
+            }
+            match line.trim().strip_prefix("palette ") {
+                Some(name) if !line.contains('=') => {
+                    palette = Some(Palette::named_or_load(name.trim()).map_err(at_line)?);
+                }
+                _ => compiler.assign(line).map_err(at_line)?,
+            }
+        }
+
+        let program = compiler.finish();
+        let output = match (
+            program.slot("r"),
+            program.slot("g"),
+            program.slot("b"),
+            program.slot("value"),
+        ) {
+            (Some(r), Some(g), Some(b), _) => Output::Rgb([r, g, b]),
+            (_, _, _, Some(value)) => Output::Value(value),
+            _ => {
+                return Err(invalid(
+                    "nothing to draw: assign `value`, or all of `r`, `g` and `b`".to_string(),
+                ))
+            }
+        };
+
+        Ok(Formula {
+            program,
+            output,
+            palette,
+        })
+    }
+}
+
+/// Draws a [`Formula`] loaded from a file, reloading it whenever the file
+/// changes.
+pub struct ExprScene {
+    watch: FileWatch,
+    formula: Formula,
+    knobs: [f32; 4],
+    palette: Option<Palette>,
+    palette_index: usize,
+    /// Used when neither the file nor the parameter picks a palette.
+    default_palette: Palette,
+}
+
+impl ExprScene {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(ExprScene {
+            formula: Formula::load(&path)?,
+            watch: FileWatch::new(&path),
+            knobs: [0.5; 4],
+            palette: None,
+            palette_index: 0,
+            default_palette: Palette::named("grayscale").unwrap(),
+        })
+    }
+
+    /// Colors `value` formulas through `palette` unless they pick their
+    /// own.
+    pub fn with_palette(mut self, palette: Palette) -> Self {
+        self.default_palette = palette;
+        self
+    }
+
+    /// Reloads the formula. A broken file is reported and the last good
+    /// formula kept, so it can be fixed while running.
+    fn reload(&mut self) {
+        let path = self.watch.path();
+
+        match Formula::load(path) {
+            Ok(formula) => {
+                self.formula = formula;
+                println!("expr: reloaded {}", path.display());
+            }
+            Err(e) => eprintln!("expr: {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl Scene for ExprScene {
+    fn name(&self) -> &str {
+        "expr"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        let mut params = knob_params(&self.knobs);
+        params.push(palette_param(self.palette_index));
+        params
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        if let Some(i) = KNOB_NAMES.iter().position(|knob| *knob == name) {
+            self.knobs[i] = value;
+        } else if name == "palette" {
+            let (index, palette) = palette_from_param(value);
+            self.palette_index = index;
+            self.palette = palette;
+        }
+    }
+
+    fn tick(&mut self, canvas: &mut Canvas, tick: &FrameTick) {
+        if self.watch.changed(tick.dt) {
+            self.reload();
+        }
+
+        let (width, height) = (canvas.width as f32, canvas.height as f32);
+        let scale = 1.0 / width.min(height).max(1.0);
+        let flag = |b: bool| if b { 1.0 } else { 0.0 };
+        let audio = &tick.audio;
+
+        let mut vars = vec![0.0; PY + 1];
+        vars.extend_from_slice(&[
+            width,
+            height,
+            tick.t,
+            tick.dt,
+            audio.level,
+            flag(audio.beat),
+            flag(audio.onset),
+        ]);
+        vars.extend_from_slice(&self.knobs);
+        vars.extend_from_slice(&audio.bands);
+        vars.extend_from_slice(&audio.envelopes);
+        debug_assert_eq!(vars.len(), BUILTINS.len());
+        vars.resize(self.formula.program.slots(), 0.0);
+
+        let formula = &self.formula;
+        let palette = self
+            .palette
+            .as_ref()
+            .or(formula.palette.as_ref())
+            .unwrap_or(&self.default_palette);
+        let canvas_width = canvas.width;
+
+        canvas.par_rows_mut(|y, row| {
+            let mut vars = vars.clone();
+            let mut stack = vec![];
+            let py = y as f32 + 0.5;
+
+            for x in 0..canvas_width {
+                let px = x as f32 + 0.5;
+                vars[X] = px / width;
+                vars[Y] = py / height;
+                vars[U] = (px - width / 2.0) * scale;
+                vars[V] = (py - height / 2.0) * scale;
+                vars[PX] = x as f32;
+                vars[PY] = y as f32;
+
+                formula.program.run(&mut vars, &mut stack);
+
+                let (r, g, b) = match formula.output {
+                    Output::Rgb([r, g, b]) => (vars[r], vars[g], vars[b]),
+                    Output::Value(value) => palette.sample(vars[value]),
+                };
+                row.set_pixel(x, r, g, b);
+            }
+        });
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_outputs() {
+        let formula = Formula::parse("# rings\npalette fire\nd = hypot(u, v)\nvalue = d * 2\n");
+        assert!(matches!(formula.unwrap().output, Output::Value(_)));
+
+        let formula = Formula::parse("r = x\ng = y\nb = 0.5 # flat\n").unwrap();
+        assert!(matches!(formula.output, Output::Rgb(_)));
+        assert!(formula.palette.is_none());
+
+        let message = |text| Formula::parse(text).err().unwrap().to_string();
+        assert_eq!(
+            message("r = x\ng = y\n"),
+            "nothing to draw: assign `value`, or all of `r`, `g` and `b`"
+        );
+        assert_eq!(
+            message("\nvalue = 1 +"),
+            "line 2: column 12: expected a value, found the end"
+        );
+        assert_eq!(
+            message("value"),
+            "line 1: column 6: expected `=`, found the end"
+        );
+        assert_eq!(
+            message("t = 1"),
+            "line 1: column 1: `t` is built in and can't be assigned"
+        );
+    }
+}
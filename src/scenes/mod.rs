@@ -1,5 +1,136 @@
-pub mod wave;
+pub mod expr;
 pub mod plasma;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod sand;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod wave;
 
-pub use self::wave::WaveScene;
+pub use self::expr::ExprScene;
 pub use self::plasma::PlasmaScene;
+#[cfg(feature = "plugins")]
+pub use self::plugin::PluginScene;
+pub use self::sand::{HourglassScene, SandScene};
+#[cfg(feature = "scripting")]
+pub use self::script::ScriptScene;
+pub use self::wave::WaveScene;
+
+use crate::Scene;
+
+/// The scenes the generator can show, and which one is showing.
+pub struct SceneList {
+    scenes: Vec<Box<dyn Scene>>,
+    current: usize,
+}
+
+impl SceneList {
+    /// Starts showing the first of `scenes`.
+    pub fn new(mut scenes: Vec<Box<dyn Scene>>) -> Self {
+        if let Some(first) = scenes.first_mut() {
+            first.on_enter();
+        }
+        SceneList { scenes, current: 0 }
+    }
+
+    pub fn current(&mut self) -> &mut (dyn Scene + 'static) {
+        self.scenes[self.current].as_mut()
+    }
+
+    pub fn next(&mut self) {
+        self.switch((self.current + 1) % self.scenes.len());
+    }
+
+    pub fn prev(&mut self) {
+        self.switch((self.current + self.scenes.len() - 1) % self.scenes.len());
+    }
+
+    /// Switches to the scene called `name`, if there is one.
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.scenes.iter().position(|scene| scene.name() == name) {
+            Some(i) => {
+                self.switch(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Tells every scene the canvas is now `width` by `height`, so hidden
+    /// scenes are ready when they're switched to.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        for scene in self.scenes.iter_mut() {
+            scene.on_resize(width, height);
+        }
+    }
+
+    fn switch(&mut self, to: usize) {
+        if to == self.current {
+            return;
+        }
+
+        self.scenes[self.current].on_exit();
+        self.current = to;
+        self.scenes[self.current].on_enter();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Scene> {
+        self.scenes.iter().map(|scene| scene.as_ref())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut (dyn Scene + 'static)> {
+        self.scenes.iter_mut().map(|scene| scene.as_mut())
+    }
+
+    pub fn get(&mut self, name: &str) -> Option<&mut (dyn Scene + 'static)> {
+        self.scenes
+            .iter_mut()
+            .find(|scene| scene.name() == name)
+            .map(|scene| scene.as_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::Disturbance;
+    use crate::{Canvas, FrameTick};
+
+    #[test]
+    fn survives_an_empty_canvas() {
+        let mut canvas = Canvas::new(8, 8);
+        let mut scenes = SceneList::new(vec![
+            Box::new(WaveScene::new(&canvas)),
+            Box::new(PlasmaScene::new()),
+            Box::new(SandScene::new(&canvas)),
+            Box::new(HourglassScene::new(&canvas)),
+        ]);
+        scenes.get("wave").unwrap().set_param("rain", 20.0);
+
+        let tick = FrameTick {
+            dt: 0.5,
+            ..FrameTick::from_start()
+        };
+        for &(width, height) in &[(0, 0), (0, 8), (8, 0), (8, 8)] {
+            canvas.resize(width, height);
+            scenes.resize(width, height);
+
+            for scene in scenes.iter_mut() {
+                scene.on_enter();
+                scene.disturb(&Disturbance::Point {
+                    x: 0.5,
+                    y: 0.5,
+                    strength: 1.0,
+                });
+                scene.disturb(&Disturbance::Line {
+                    from: (0.0, 0.0),
+                    to: (1.0, 1.0),
+                    strength: 1.0,
+                });
+                for _ in 0..4 {
+                    scene.tick(&mut canvas, &tick);
+                }
+            }
+        }
+    }
+}
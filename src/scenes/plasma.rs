@@ -1,35 +1,312 @@
+use crate::gradient::Palette;
+use crate::params::{palette_from_param, palette_param, Param};
 use crate::{Canvas, FrameTick, Scene};
 
-pub struct PlasmaScene {}
+/// Waves across the shorter side of the canvas at a zoom of 1.
+const FREQUENCY: f32 = 6.0;
+
+/// The formula a plasma is drawn with.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Variant {
+    /// The original plasma: a sine of a drifting slope plus two wandering
+    /// rings.
+    Classic,
+    /// Sines along several directions added up.
+    SineSum,
+    /// A spiral around a wandering center.
+    Radial,
+    /// Ripples from three moving sources crossing each other.
+    Interference,
+    /// Two sines bending each other, tracing Lissajous figures.
+    Lissajous,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 5] = [
+        Variant::Classic,
+        Variant::SineSum,
+        Variant::Radial,
+        Variant::Interference,
+        Variant::Lissajous,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Classic => "classic",
+            Variant::SineSum => "sines",
+            Variant::Radial => "radial",
+            Variant::Interference => "interference",
+            Variant::Lissajous => "lissajous",
+        }
+    }
+
+    pub fn named(name: &str) -> Option<Variant> {
+        Variant::ALL
+            .iter()
+            .copied()
+            .find(|variant| variant.name() == name)
+    }
+
+    /// The plasma at (`x`, `y`) at time `t`, in `-1.0..=1.0`. `x` and `y`
+    /// are centered on the canvas and scaled so its shorter side is `1.0`
+    /// long, then multiplied by [`FREQUENCY`].
+    fn value(self, x: f32, y: f32, t: f32) -> f32 {
+        match self {
+            Variant::Classic => {
+                let (x, y) = classic_coords(x, y, t);
+
+                let slope = ((0.25 * t).sin() * x + (0.29 * t).cos() * y + t).sin();
+                let ring1 =
+                    ((x + (t * 0.25).sin() * 4.0).hypot(y + (t * 0.43).cos() * 4.0) + t).sin();
+                let ring2 =
+                    ((x + (t * 0.36).cos() * 6.0).hypot(y + (t * 0.39).sin() * 5.3) + t).cos();
+
+                (slope + ring1 - ring2).sin()
+            }
+            Variant::SineSum => {
+                ((x + t).sin()
+                    + (y * 0.8 - t * 0.7).sin()
+                    + ((x + y) * 0.7 + t * 1.3).sin()
+                    + ((x - y) * 0.5 - t * 0.9).sin())
+                    / 4.0
+            }
+            Variant::Radial => {
+                let (cx, cy) = ((t * 0.3).sin() * 2.0, (t * 0.23).cos() * 2.0);
+                let (dx, dy) = (x - cx, y - cy);
+                (dx.hypot(dy) - t * 2.0 + dy.atan2(dx) * 3.0).sin()
+            }
+            Variant::Interference => {
+                let sources = [
+                    ((t * 0.31).sin() * 4.0, (t * 0.27).cos() * 3.0),
+                    ((t * 0.23 + 2.0).sin() * 4.0, (t * 0.37 + 1.0).cos() * 3.0),
+                    ((t * 0.19 + 4.0).cos() * 4.0, (t * 0.29 + 3.0).sin() * 3.0),
+                ];
+                sources
+                    .iter()
+                    .map(|&(sx, sy)| ((x - sx).hypot(y - sy) * 1.5 - t * 3.0).sin())
+                    .sum::<f32>()
+                    / 3.0
+            }
+            Variant::Lissajous => {
+                let a = (x * 0.9 + (y * 0.5 + t).sin() * 1.5).sin();
+                let b = (y * 0.7 + (x * 0.4 - t * 0.8).cos() * 1.5).cos();
+                a * b
+            }
+        }
+    }
+}
+
+/// The drifting, breathing coordinates the classic plasma is drawn in.
+fn classic_coords(x: f32, y: f32, t: f32) -> (f32, f32) {
+    let scale = 0.6 + (t * 0.25).sin() * 0.1;
+    (
+        x * scale + (t * 0.25).sin() * 5.0,
+        y * scale + (t * 0.25).cos() * 5.0,
+    )
+}
+
+/// The classic plasma's own coloring, from before there were palettes.
+fn classic_color(x: f32, y: f32, t: f32, value: f32) -> (f32, f32, f32) {
+    let (x, y) = classic_coords(x, y, t);
+    let u = ((9.0 * value + 0.5 * x + t).cos() * 0.5 + 0.5).powi(2);
+    let v = ((9.0 * value + 0.5 * y + t).sin() * 0.5 + 0.5).powi(2);
+
+    (u, v, (u + v) / 2.0)
+}
+
+pub struct PlasmaScene {
+    variant: Variant,
+    /// Scale of the pattern; higher zooms in.
+    zoom: f32,
+    palette: Option<Palette>,
+    palette_index: usize,
+    /// Used when no palette is chosen, except by [`Variant::Classic`] which
+    /// has colors of its own.
+    default_palette: Palette,
+    speed: f32,
+    t: f32,
+}
+
+impl Default for PlasmaScene {
+    fn default() -> Self {
+        PlasmaScene::new()
+    }
+}
+
+impl PlasmaScene {
+    pub fn new() -> Self {
+        PlasmaScene {
+            variant: Variant::Classic,
+            zoom: 1.0,
+            palette: None,
+            palette_index: 0,
+            default_palette: Palette::named("rainbow").unwrap(),
+            speed: 0.5,
+            t: 0.0,
+        }
+    }
+
+    pub fn with_variant(mut self, variant: Variant) -> Self {
+        self.variant = variant;
+        self
+    }
+
+    /// Colors the plasma through `palette` instead of its default colors.
+    pub fn with_palette(mut self, palette: Palette) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+}
 
 impl Scene for PlasmaScene {
+    fn name(&self) -> &str {
+        "plasma"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![
+            Param::new(
+                "variant",
+                0.0,
+                (Variant::ALL.len() - 1) as f32,
+                Variant::ALL
+                    .iter()
+                    .position(|&variant| variant == self.variant)
+                    .unwrap() as f32,
+            ),
+            Param::new("zoom", 0.25, 4.0, self.zoom),
+            palette_param(self.palette_index),
+            Param::new("speed", 0.0, 2.0, self.speed),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        match name {
+            "variant" => {
+                let index = (value.round().max(0.0) as usize).min(Variant::ALL.len() - 1);
+                self.variant = Variant::ALL[index];
+            }
+            "zoom" => self.zoom = value,
+            "palette" => {
+                let (index, palette) = palette_from_param(value);
+                self.palette_index = index;
+                self.palette = palette;
+            }
+            "speed" => self.speed = value,
+            _ => {}
+        }
+    }
+
     fn tick(&mut self, canvas: &mut Canvas, tick: &FrameTick) {
-        let t = tick.start.elapsed().as_secs_f32() * 0.5f32;
-
-        for y in 0..canvas.height {
-            for x in 0..canvas.width {
-                let xp =
-                    ((x as f32 / 128.0) - 0.5) * (5.0 + (t * 0.25).sin()) + (t * 0.25).sin() * 5.0;
-                let yp =
-                    ((y as f32 / 128.0) - 0.5) * (5.0 + (t * 0.25).sin()) + (t * 0.25).cos() * 5.0;
-
-                let pixel = (((0.25 * t).sin() * xp + (0.29 * t).cos() * yp + t).sin()
-                    + (((xp + (t * 0.25).sin() * 4.0).powf(2.0)
-                        + (yp + (t * 0.43).cos() * 4.0).powf(2.0))
-                    .sqrt()
-                        + t)
-                        .sin()
-                    - (((xp + (t * 0.36).cos() * 6.0).powf(2.0)
-                        + (yp + (t * 0.39).sin() * 5.3).powf(2.0))
-                    .sqrt()
-                        + t)
-                        .cos())
-                .sin();
-
-                let u = ((9.0 * pixel + 0.5 * xp + t).cos() * 0.5 + 0.5).powf(2.0);
-                let v = ((9.0 * pixel + 0.5 * yp + t).sin() * 0.5 + 0.5).powf(2.0);
-
-                canvas.set_pixel(x, y, u, v, (u + v) / 2.0)
+        // Accumulated rather than taken from the start time, so changing
+        // the speed doesn't jump.
+        self.t += tick.dt * self.speed;
+        let t = self.t;
+
+        let (width, height) = (canvas.width, canvas.height);
+        // Cells are square, so scaling both axes by the shorter side keeps
+        // circles round on any canvas.
+        let scale = FREQUENCY / (self.zoom * width.min(height).max(1) as f32);
+        let (center_x, center_y) = (width as f32 / 2.0, height as f32 / 2.0);
+
+        let variant = self.variant;
+        let palette = self.palette.as_ref();
+        let default_palette = &self.default_palette;
+
+        canvas.par_rows_mut(|y, row| {
+            let py = (y as f32 + 0.5 - center_y) * scale;
+
+            for x in 0..width {
+                let px = (x as f32 + 0.5 - center_x) * scale;
+                let value = variant.value(px, py, t);
+
+                let (r, g, b) = match (palette, variant) {
+                    (Some(palette), _) => palette.sample(value * 0.5 + 0.5),
+                    (None, Variant::Classic) => classic_color(px, py, t, value),
+                    (None, _) => default_palette.sample(value * 0.5 + 0.5),
+                };
+                row.set_pixel(x, r, g, b);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The plasma drawn on a `width` by `height` canvas at time `t`.
+    fn draw(scene: &mut PlasmaScene, width: u32, height: u32, t: f32) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        scene.set_param("speed", 1.0);
+        scene.t = 0.0;
+        let tick = FrameTick {
+            dt: t,
+            ..FrameTick::from_start()
+        };
+        scene.tick(&mut canvas, &tick);
+        canvas
+    }
+
+    fn assert_close(a: &[u8], b: &[u8]) {
+        assert_eq!(a.len(), b.len());
+        for (i, (a, b)) in a.iter().zip(b).enumerate() {
+            assert!(
+                (*a as i32 - *b as i32).abs() <= 1,
+                "byte {}: {} != {}",
+                i,
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn classic_keeps_its_colors() {
+        // The original plasma's u, v and (u + v) / 2 channels.
+        let canvas = draw(&mut PlasmaScene::new(), 4, 2, 1.0);
+        assert_close(
+            canvas.pixels(),
+            &[
+                89, 1, 45, 201, 166, 183, 95, 18, 57, 75, 251, 163, //
+                251, 26, 138, 26, 189, 107, 250, 232, 241, 126, 32, 79,
+            ],
+        );
+    }
+
+    #[test]
+    fn variants_differ() {
+        let mut scene = PlasmaScene::new();
+        let drawn: Vec<Vec<u8>> = Variant::ALL
+            .iter()
+            .map(|&variant| {
+                scene.variant = variant;
+                draw(&mut scene, 8, 8, 2.0).pixels().to_vec()
+            })
+            .collect();
+
+        for (i, a) in drawn.iter().enumerate() {
+            for b in &drawn[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn wide_canvases_are_not_stretched() {
+        for &variant in Variant::ALL.iter() {
+            let mut scene = PlasmaScene::new().with_variant(variant);
+            let square = draw(&mut scene, 16, 16, 3.0);
+            let wide = draw(&mut scene, 32, 16, 3.0);
+            let tall = draw(&mut scene, 16, 32, 3.0);
+
+            // The square canvas is the middle of both.
+            for y in 0..16 {
+                let row = &square.pixels()[y * 16 * 3..(y + 1) * 16 * 3];
+                let wide_start = (y * 32 + 8) * 3;
+                assert_close(row, &wide.pixels()[wide_start..wide_start + 16 * 3]);
+                let tall_start = (y + 8) * 16 * 3;
+                assert_close(row, &tall.pixels()[tall_start..tall_start + 16 * 3]);
             }
         }
     }
@@ -0,0 +1,340 @@
+//! Scenes loaded from WebAssembly modules, run sandboxed with
+//! [wasmi](https://github.com/wasmi-labs/wasmi).
+//!
+//! # ABI, version 1
+//!
+//! A plugin exports:
+//!
+//! - `memory`: its linear memory.
+//! - `matryx_abi() -> i32`: the ABI version it was built for, `1`.
+//! - `matryx_init(width: i32, height: i32) -> i32`: called when it's loaded,
+//!   and again whenever the canvas is resized. Returns the offset in
+//!   `memory` of its frame buffer, which holds `width * height * 3` bytes:
+//!   red, green and blue for each pixel, row by row from the top left, the
+//!   same as [`Canvas::pixels`].
+//! - `matryx_tick(t: f32, dt: f32)`: draws the next frame into the buffer.
+//!   `t` is seconds since start and `dt` seconds since the last frame.
+//!
+//! and can import, from `env`:
+//!
+//! - `matryx_param(index: i32) -> f32`: the value of parameter `p1` to `p4`,
+//!   at index 0 to 3, in `0.0..=1.0`. Other indexes give 0.
+//! - `matryx_log(offset: i32, length: i32)`: prints `length` bytes of UTF-8
+//!   text from `memory`.
+//!
+//! Later versions will only add imports, so a plugin built for version 1
+//! keeps working as long as it reports it.
+//!
+//! A plugin's memory can't grow past [`MAX_MEMORY`], and each call can run
+//! about [`FUEL_PER_CALL`] instructions before it's stopped. A plugin that
+//! traps or runs out is reported and not called again.
+
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use wasmi::{
+    Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+    TypedFunc, WasmParams, WasmResults,
+};
+
+use crate::params::{knob_params, Param, KNOB_NAMES};
+use crate::{Canvas, FrameTick, Scene};
+
+pub const ABI_VERSION: i32 = 1;
+
+/// Bytes of memory a plugin may use.
+pub const MAX_MEMORY: usize = 16 << 20;
+/// Instructions, roughly, that a plugin may run per call.
+pub const FUEL_PER_CALL: u64 = 50_000_000;
+
+/// Color the canvas is filled with once the plugin has failed.
+const ERROR_COLOR: (f32, f32, f32) = (0.3, 0.0, 0.0);
+
+/// What the host functions can reach.
+struct Host {
+    name: String,
+    limits: StoreLimits,
+    knobs: [f32; 4],
+}
+
+pub struct PluginScene {
+    store: Store<Host>,
+    memory: Memory,
+    init: TypedFunc<(i32, i32), i32>,
+    tick: TypedFunc<(f32, f32), ()>,
+    /// Offset of the frame buffer in `memory`.
+    frame: usize,
+    /// Set once the plugin fails, after which it's left alone.
+    failed: bool,
+}
+
+impl PluginScene {
+    /// Loads a plugin, named after its file.
+    pub fn load<P: AsRef<Path>>(path: P, canvas: &Canvas) -> io::Result<Self> {
+        let path = path.as_ref();
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        PluginScene::new(name, &fs::read(path)?, canvas)
+    }
+
+    /// Instantiates a plugin from the bytes of a module, and calls its
+    /// `matryx_init`.
+    pub fn new(name: String, wasm: &[u8], canvas: &Canvas) -> io::Result<Self> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm).map_err(invalid)?;
+
+        let host = Host {
+            name,
+            limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build(),
+            knobs: [0.5; 4],
+        };
+        let mut store = Store::new(&engine, host);
+        store.limiter(|host| &mut host.limits);
+
+        let mut linker = Linker::new(&engine);
+        linker
+            .func_wrap(
+                "env",
+                "matryx_param",
+                |caller: Caller<'_, Host>, index: i32| {
+                    let knobs = &caller.data().knobs;
+                    knobs.get(index as u32 as usize).copied().unwrap_or(0.0)
+                },
+            )
+            .map_err(invalid)?;
+        linker
+            .func_wrap(
+                "env",
+                "matryx_log",
+                |caller: Caller<'_, Host>, offset: i32, length: i32| {
+                    let memory = match caller.get_export("memory").and_then(Extern::into_memory) {
+                        Some(memory) => memory,
+                        None => return,
+                    };
+                    let (start, end) = (
+                        offset as u32 as usize,
+                        offset as u32 as usize + length as u32 as usize,
+                    );
+                    if let Some(text) = memory.data(&caller).get(start..end) {
+                        println!("{}: {}", caller.data().name, String::from_utf8_lossy(text));
+                    }
+                },
+            )
+            .map_err(invalid)?;
+
+        store.set_fuel(FUEL_PER_CALL).map_err(invalid)?;
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(invalid)?;
+
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| invalid("no `memory` export"))?;
+        let abi: TypedFunc<(), i32> = export(&instance, &store, "matryx_abi")?;
+        let init: TypedFunc<(i32, i32), i32> = export(&instance, &store, "matryx_init")?;
+        let tick = export(&instance, &store, "matryx_tick")?;
+
+        store.set_fuel(FUEL_PER_CALL).map_err(invalid)?;
+        let version = abi.call(&mut store, ()).map_err(invalid)?;
+        if version != ABI_VERSION {
+            return Err(invalid(format!(
+                "built for ABI version {}, expected {}",
+                version, ABI_VERSION
+            )));
+        }
+
+        let mut scene = PluginScene {
+            store,
+            memory,
+            init,
+            tick,
+            frame: 0,
+            failed: false,
+        };
+        scene.init(canvas.width, canvas.height)?;
+
+        Ok(scene)
+    }
+
+    /// Calls `matryx_init` for a `width` by `height` canvas.
+    fn init(&mut self, width: u32, height: u32) -> io::Result<()> {
+        self.store.set_fuel(FUEL_PER_CALL).map_err(invalid)?;
+        let frame = self
+            .init
+            .call(&mut self.store, (width as i32, height as i32))
+            .map_err(invalid)?;
+
+        self.frame = frame as u32 as usize;
+        match self.frame_buffer((width * height * 3) as usize) {
+            Some(_) => Ok(()),
+            None => Err(invalid("frame buffer doesn't fit in memory")),
+        }
+    }
+
+    fn frame_buffer(&self, length: usize) -> Option<&[u8]> {
+        self.memory
+            .data(&self.store)
+            .get(self.frame..self.frame.checked_add(length)?)
+    }
+
+    fn fail<E: Display>(&mut self, e: E) {
+        eprintln!("plugin: {}: {}", self.store.data().name, e);
+        self.failed = true;
+    }
+}
+
+/// Looks up an exported function with the signature the ABI expects.
+fn export<Params: WasmParams, Results: WasmResults>(
+    instance: &wasmi::Instance,
+    store: &Store<Host>,
+    name: &str,
+) -> io::Result<TypedFunc<Params, Results>> {
+    instance
+        .get_typed_func(store, name)
+        .map_err(|e| invalid(format!("bad `{}` export: {}", name, e)))
+}
+
+impl Scene for PluginScene {
+    fn name(&self) -> &str {
+        &self.store.data().name
+    }
+
+    fn params(&self) -> Vec<Param> {
+        knob_params(&self.store.data().knobs)
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        if let Some(i) = KNOB_NAMES.iter().position(|knob| *knob == name) {
+            self.store.data_mut().knobs[i] = value;
+        }
+    }
+
+    fn on_resize(&mut self, width: u32, height: u32) {
+        if !self.failed {
+            if let Err(e) = self.init(width, height) {
+                self.fail(e);
+            }
+        }
+    }
+
+    fn tick(&mut self, canvas: &mut Canvas, tick: &FrameTick) {
+        if !self.failed {
+            self.store.set_fuel(FUEL_PER_CALL).unwrap();
+            if let Err(e) = self.tick.call(&mut self.store, (tick.t, tick.dt)) {
+                self.fail(e);
+            }
+        }
+
+        if !self.failed {
+            match self.frame_buffer(canvas.pixels.len()) {
+                Some(frame) => {
+                    canvas.pixels.copy_from_slice(frame);
+                    return;
+                }
+                None => self.fail("frame buffer doesn't fit in memory"),
+            }
+        }
+
+        let (r, g, b) = ERROR_COLOR;
+        canvas.clear_with_color(r, g, b);
+    }
+}
+
+fn invalid<E: Display>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRAW: &str = "
+        (func (export \"matryx_tick\") (param f32 f32)
+            ;; The first pixel's red is `p1`, and the second's blue counts
+            ;; frames.
+            (i32.store8 (i32.const 16)
+                (i32.trunc_f32_u (f32.mul (call $param (i32.const 0)) (f32.const 255))))
+            (i32.store8 (i32.const 21)
+                (i32.add (i32.load8_u (i32.const 21)) (i32.const 1))))";
+
+    /// A plugin with `pages` of memory, reporting ABI `version`, whose
+    /// frame buffer is at offset 16, plus `rest`.
+    fn plugin(pages: usize, version: i32, rest: &str) -> Vec<u8> {
+        wat::parse_str(format!(
+            r#"(module
+                (import "env" "matryx_param" (func $param (param i32) (result f32)))
+                (memory (export "memory") {})
+                (func (export "matryx_abi") (result i32) i32.const {})
+                (func (export "matryx_init") (param i32 i32) (result i32) i32.const 16)
+                {})"#,
+            pages, version, rest
+        ))
+        .unwrap()
+    }
+
+    fn load(wasm: &[u8]) -> io::Result<(PluginScene, Canvas)> {
+        let canvas = Canvas::new(2, 1);
+        PluginScene::new("test".to_string(), wasm, &canvas).map(|scene| (scene, canvas))
+    }
+
+    #[test]
+    fn draws_frames() {
+        let (mut scene, mut canvas) = load(&plugin(1, 1, DRAW)).unwrap();
+        scene.set_param("p1", 0.2);
+
+        scene.tick(&mut canvas, &FrameTick::from_start());
+        scene.tick(&mut canvas, &FrameTick::from_start());
+        assert_eq!(canvas.pixels(), &[51, 0, 0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn initializes_again_when_resized() {
+        let (mut scene, mut canvas) = load(&plugin(1, 1, DRAW)).unwrap();
+        scene.set_param("p1", 0.2);
+
+        canvas.resize(3, 2);
+        scene.on_resize(3, 2);
+        scene.tick(&mut canvas, &FrameTick::from_start());
+        assert!(!scene.failed);
+        assert_eq!(&canvas.pixels()[..6], &[51, 0, 0, 0, 0, 1]);
+        assert_eq!(canvas.pixels().len(), 18);
+
+        // Past the end of its memory.
+        scene.on_resize(256, 256);
+        assert!(scene.failed);
+    }
+
+    #[test]
+    fn checks_the_abi() {
+        let message = |wasm: &[u8]| load(wasm).err().unwrap().to_string();
+
+        assert_eq!(
+            message(&plugin(1, 2, DRAW)),
+            "built for ABI version 2, expected 1"
+        );
+        assert!(message(&plugin(1, 1, "")).starts_with("bad `matryx_tick` export"));
+        assert!(message(&plugin(1, 1, "(func (export \"matryx_tick\"))"))
+            .starts_with("bad `matryx_tick` export"));
+    }
+
+    #[test]
+    fn limits_cpu_and_memory() {
+        let endless = "(func (export \"matryx_tick\") (param f32 f32) (loop $l (br $l)))";
+        let (mut scene, mut canvas) = load(&plugin(1, 1, endless)).unwrap();
+        scene.tick(&mut canvas, &FrameTick::from_start());
+        assert!(scene.failed);
+        assert_eq!(&canvas.pixels()[..3], &[76, 0, 0]);
+
+        let pages = MAX_MEMORY / 65536;
+        assert!(load(&plugin(pages, 1, DRAW)).is_ok());
+        assert!(load(&plugin(pages + 1, 1, DRAW)).is_err());
+    }
+}
@@ -0,0 +1,386 @@
+use std::f32::consts::TAU;
+
+use crate::control::Event;
+use crate::grid::{Boundary, Grid, Neighborhood};
+use crate::params::Param;
+use crate::{Canvas, FrameTick, Scene};
+
+use super::material::Material;
+use super::render;
+use super::world::World;
+
+/// Share of the top chamber that's filled with sand.
+const FILL: f32 = 0.7;
+/// Seconds the hourglass rests after running out before it turns itself
+/// over, so the last grains can settle.
+const FLIP_DELAY: f32 = 3.0;
+const OUTLINE_COLOR: (f32, f32, f32) = (0.3, 0.25, 0.2);
+
+/// Cells of one half of the glass, nearest the neck first.
+type Chamber = Vec<(i32, i32)>;
+
+/// A sand timer. The neck is shut, and grains are passed through it at a
+/// metered rate so the top chamber empties in exactly `duration` seconds.
+pub struct HourglassScene {
+    world: World,
+    /// Whether each cell is part of the drawn glass outline.
+    outline: Grid<bool>,
+    top: Chamber,
+    bottom: Chamber,
+
+    /// Grains that started in the top chamber.
+    grains: usize,
+    /// Grains passed through the neck so far.
+    passed: usize,
+    /// Seconds since the hourglass was last turned over.
+    elapsed: f32,
+    finished: bool,
+
+    /// Seconds the sand takes to run through.
+    duration: f32,
+    auto_flip: bool,
+    on_end: Option<Box<dyn FnMut()>>,
+}
+
+impl HourglassScene {
+    pub fn new(canvas: &Canvas) -> Self {
+        let (world, outline, top, bottom) =
+            glass(canvas.width() as usize, canvas.height() as usize);
+
+        let mut scene = HourglassScene {
+            world,
+            outline,
+            top,
+            bottom,
+            grains: 0,
+            passed: 0,
+            elapsed: 0.0,
+            finished: false,
+            duration: 300.0,
+            auto_flip: true,
+            on_end: None,
+        };
+        scene.fill(1.0);
+        scene
+    }
+
+    /// Pours sand into the glass, with `share` of it in the top chamber and
+    /// the rest already run through.
+    fn fill(&mut self, share: f32) {
+        let mut rng = rand::thread_rng();
+
+        self.grains = (self.top.len() as f32 * FILL) as usize;
+        let left = ((self.grains as f32 * share).round() as usize).min(self.grains);
+        self.passed = self.grains - left;
+
+        for &(x, y) in &self.top[..left] {
+            self.world.place(x, y, Material::Sand, &mut rng);
+        }
+        // The bottom chamber fills from the far end, where the sand rests.
+        for &(x, y) in self.bottom.iter().rev().take(self.passed) {
+            self.world.place(x, y, Material::Sand, &mut rng);
+        }
+    }
+
+    /// Calls `on_end` each time the sand runs out. A glass too small to
+    /// hold any sand never runs out.
+    pub fn with_on_end<F: FnMut() + 'static>(mut self, on_end: F) -> Self {
+        self.on_end = Some(Box::new(on_end));
+        self
+    }
+
+    /// Moves the grain nearest the neck into the bottom chamber, returning
+    /// false if there's no grain or no room.
+    fn release(&mut self) -> bool {
+        let map = self.world.map();
+        let from = self
+            .top
+            .iter()
+            .find(|&&(x, y)| !map[(x as usize, y as usize)].is_empty());
+        let to = self
+            .bottom
+            .iter()
+            .find(|&&(x, y)| map[(x as usize, y as usize)].is_empty());
+
+        match (from, to) {
+            (Some(&from), Some(&to)) => {
+                self.world.swap(from, to);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Turns the hourglass over and starts timing again.
+    fn flip(&mut self) {
+        self.world.flip_vertical();
+
+        let map = self.world.map();
+        self.grains = self
+            .top
+            .iter()
+            .filter(|&&(x, y)| !map[(x as usize, y as usize)].is_empty())
+            .count();
+        self.passed = 0;
+        self.elapsed = 0.0;
+        self.finished = false;
+    }
+
+    fn draw(&self, canvas: &mut Canvas) {
+        let outline = if self.finished {
+            // Pulse towards white once the time is up.
+            let pulse = 0.5 - ((self.elapsed - self.duration) * TAU).cos() * 0.5;
+            let (r, g, b) = OUTLINE_COLOR;
+            (
+                r + (1.0 - r) * pulse,
+                g + (1.0 - g) * pulse,
+                b + (1.0 - b) * pulse,
+            )
+        } else {
+            OUTLINE_COLOR
+        };
+
+        for (y, row) in self.world.map().rows().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let (r, g, b) = if self.outline[(x, y)] {
+                    outline
+                } else if tile.material == Material::Sand {
+                    render::shaded(Material::Sand.color(), tile)
+                } else {
+                    (0.0, 0.0, 0.0)
+                };
+                canvas.set_pixel(x as u32, y as u32, r, g, b);
+            }
+        }
+    }
+}
+
+impl Scene for HourglassScene {
+    fn name(&self) -> &str {
+        "hourglass"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![
+            Param::new("duration", 1.0, 3600.0, self.duration),
+            Param::new("auto_flip", 0.0, 1.0, self.auto_flip as u8 as f32),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        match name {
+            "duration" => self.duration = value,
+            "auto_flip" => self.auto_flip = value >= 0.5,
+            _ => {}
+        }
+    }
+
+    /// Builds the glass again at the new size, with as much of the sand
+    /// left to run as before, so the timer keeps going.
+    fn on_resize(&mut self, width: u32, height: u32) {
+        let share = if self.grains == 0 {
+            1.0
+        } else {
+            (self.grains - self.passed) as f32 / self.grains as f32
+        };
+
+        let (world, outline, top, bottom) = glass(width as usize, height as usize);
+        self.world = world;
+        self.outline = outline;
+        self.top = top;
+        self.bottom = bottom;
+        self.fill(share);
+    }
+
+    /// `flip` turns the hourglass over.
+    fn on_event(&mut self, event: &Event) {
+        if event.name == "flip" {
+            self.flip();
+        }
+    }
+
+    fn tick(&mut self, canvas: &mut Canvas, tick: &FrameTick) {
+        let mut rng = rand::thread_rng();
+        self.elapsed += tick.dt;
+
+        let progress = (self.elapsed / self.duration).min(1.0);
+        let due = (progress * self.grains as f32) as usize;
+        while self.passed < due && self.release() {
+            self.passed += 1;
+        }
+
+        let ran_out = self.grains > 0 && self.passed == self.grains;
+        if !self.finished && ran_out && self.elapsed >= self.duration {
+            self.finished = true;
+            if let Some(on_end) = &mut self.on_end {
+                on_end();
+            }
+        }
+
+        if self.finished && self.auto_flip && self.elapsed >= self.duration + FLIP_DELAY {
+            self.flip();
+        }
+
+        self.world.step(&mut rng);
+        self.draw(canvas);
+    }
+}
+
+/// The glass filling a `width` by `height` map: the world with its walls
+/// placed, which cells are drawn as its outline, and the top and bottom
+/// chambers.
+fn glass(width: usize, height: usize) -> (World, Grid<bool>, Chamber, Chamber) {
+    let mut rng = rand::thread_rng();
+    let mut world = World::new(width, height);
+    if width == 0 || height == 0 {
+        return (world, Grid::new(width, height, false), vec![], vec![]);
+    }
+
+    let center_x = (width / 2) as i32;
+    let center_y = (height as f32 - 1.0) / 2.0;
+    let max_half_width = ((width as f32 - 3.0) / 2.0).min(center_y).max(0.0);
+
+    let mut inside = Grid::new(width, height, false).with_boundary(Boundary::Constant(false));
+    let mut top = vec![];
+    let mut bottom = vec![];
+
+    for y in 1..height.saturating_sub(1) {
+        let from_neck = (y as f32 - center_y).abs();
+        if from_neck < 1.0 {
+            continue;
+        }
+
+        let half_width = (from_neck / center_y * max_half_width).round() as i32;
+        for x in center_x - half_width..=center_x + half_width {
+            inside[(x as usize, y)] = true;
+            if (y as f32) < center_y {
+                top.push((x, y as i32));
+            } else {
+                bottom.push((x, y as i32));
+            }
+        }
+    }
+
+    let from_neck =
+        |&(x, y): &(i32, i32)| ((y as f32 - center_y).abs() as i32, (x - center_x).abs());
+    top.sort_by_key(from_neck);
+    bottom.sort_by_key(from_neck);
+
+    let mut outline = Grid::new(width, height, false);
+    for y in 0..height {
+        for x in 0..width {
+            if inside[(x, y)] {
+                continue;
+            }
+
+            world.place(x as i32, y as i32, Material::Stone, &mut rng);
+            outline[(x, y)] = inside
+                .neighbors(x as i32, y as i32, Neighborhood::Moore(1))
+                .any(|(_, inside)| inside);
+        }
+    }
+
+    (world, outline, top, bottom)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    const DT: f32 = 1.0 / 30.0;
+
+    fn top_grains(scene: &HourglassScene) -> usize {
+        let map = scene.world.map();
+        scene
+            .top
+            .iter()
+            .filter(|&&(x, y)| !map[(x as usize, y as usize)].is_empty())
+            .count()
+    }
+
+    /// A scene timing `duration` seconds, and how often it has run out.
+    fn hourglass(
+        width: u32,
+        height: u32,
+        duration: f32,
+    ) -> (HourglassScene, Canvas, Rc<Cell<u32>>) {
+        let canvas = Canvas::new(width, height);
+        let ends = Rc::new(Cell::new(0));
+        let counter = ends.clone();
+        let mut scene =
+            HourglassScene::new(&canvas).with_on_end(move || counter.set(counter.get() + 1));
+        scene.set_param("duration", duration);
+
+        (scene, canvas, ends)
+    }
+
+    fn tick() -> FrameTick {
+        FrameTick {
+            dt: DT,
+            ..FrameTick::from_start()
+        }
+    }
+
+    #[test]
+    fn runs_out_on_time() {
+        let duration = 2.0;
+        let (mut scene, mut canvas, ends) = hourglass(16, 32, duration);
+        let grains = top_grains(&scene);
+        assert!(grains > 0);
+
+        let tick = tick();
+        let mut frames = 0;
+        while top_grains(&scene) > 0 {
+            scene.tick(&mut canvas, &tick);
+            frames += 1;
+            assert!(frames as f32 * DT <= duration + DT * 1.5, "still running");
+        }
+
+        let emptied = frames as f32 * DT;
+        assert!(
+            (emptied - duration).abs() <= DT * 1.5,
+            "emptied at {}s",
+            emptied
+        );
+        assert_eq!(ends.get(), 1);
+        assert_eq!(scene.passed, grains);
+    }
+
+    #[test]
+    fn turns_over_after_running_out() {
+        let duration = 1.0;
+        let (mut scene, mut canvas, ends) = hourglass(16, 32, duration);
+        let grains = top_grains(&scene);
+
+        let tick = tick();
+        let frames = ((duration + FLIP_DELAY) / DT) as usize + 2;
+        for _ in 0..frames {
+            scene.tick(&mut canvas, &tick);
+        }
+
+        // Turned over, the sand that ran through is on top again.
+        assert!(!scene.finished);
+        assert_eq!(top_grains(&scene), grains);
+        assert_eq!(ends.get(), 1);
+
+        for _ in 0..frames {
+            scene.tick(&mut canvas, &tick);
+        }
+        assert_eq!(ends.get(), 2);
+    }
+
+    #[test]
+    fn tiny_glasses_never_run_out() {
+        let (mut scene, mut canvas, ends) = hourglass(3, 4, 0.1);
+        assert_eq!(top_grains(&scene), 0);
+
+        let tick = tick();
+        for _ in 0..300 {
+            scene.tick(&mut canvas, &tick);
+        }
+        assert_eq!(ends.get(), 0);
+    }
+}
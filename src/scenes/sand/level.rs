@@ -0,0 +1,228 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use super::material::Material;
+
+/// Mask color for walls.
+const WALL: [u8; 3] = [0xff, 0xff, 0xff];
+/// Mask color for drains, which remove whatever lands on them.
+const DRAIN: [u8; 3] = [0xff, 0x00, 0x00];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Cell {
+    Empty,
+    Wall,
+    Drain,
+    Emitter { material: Material, rate: f32 },
+}
+
+/// A spot that keeps adding grains.
+#[derive(Copy, Clone, Debug)]
+pub struct Emitter {
+    pub x: usize,
+    pub y: usize,
+    pub material: Material,
+    /// Grains per second.
+    pub rate: f32,
+    /// Grains owed from previous frames, so low rates still pour.
+    pub carry: f32,
+}
+
+/// Walls, drains and emitters for the sand scene, drawn as an image.
+#[derive(Clone)]
+pub struct Level {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Level {
+    /// Loads a level from a PNG mask. Pixels are read as:
+    ///
+    /// - `#ffffff`: wall.
+    /// - `#ff0000`: drain.
+    /// - `#RRffMM`: emitter pouring `RR` grains per second of material
+    ///   number `MM`, numbered as in the scene's `material` parameter.
+    /// - Anything else, including transparent pixels and `#RRffMM` without
+    ///   a material `MM`: empty.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Level> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+        let mut reader = decoder.read_info().map_err(invalid)?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(invalid)?;
+        let bytes = &buffer[..info.buffer_size()];
+
+        let pixels: Vec<[u8; 4]> = match info.color_type {
+            png::ColorType::Rgba => bytes.chunks(4).map(|p| [p[0], p[1], p[2], p[3]]).collect(),
+            png::ColorType::Rgb => bytes.chunks(3).map(|p| [p[0], p[1], p[2], 0xff]).collect(),
+            png::ColorType::GrayscaleAlpha => {
+                bytes.chunks(2).map(|p| [p[0], p[0], p[0], p[1]]).collect()
+            }
+            png::ColorType::Grayscale => bytes.iter().map(|&v| [v, v, v, 0xff]).collect(),
+            color_type => {
+                return Err(invalid(format!("unsupported color type {:?}", color_type)));
+            }
+        };
+
+        let cells = pixels.iter().map(|&pixel| cell(pixel)).collect();
+
+        Ok(Level {
+            width: info.width as usize,
+            height: info.height as usize,
+            cells,
+        })
+    }
+
+    /// A level from RGBA pixels, row by row, read as in [`Level::load`].
+    #[cfg(test)]
+    pub(super) fn from_pixels(width: usize, pixels: &[[u8; 4]]) -> Level {
+        Level {
+            width,
+            height: pixels.len() / width,
+            cells: pixels.iter().map(|&pixel| cell(pixel)).collect(),
+        }
+    }
+
+    /// The level's cell under (`x`, `y`) of a `width` by `height` map it's
+    /// stretched over.
+    fn cell_at(&self, x: usize, y: usize, width: usize, height: usize) -> Cell {
+        let lx = x * self.width / width;
+        let ly = y * self.height / height;
+        self.cells[ly * self.width + lx]
+    }
+
+    /// Wall positions on a `width` by `height` map.
+    pub fn walls(&self, width: usize, height: usize) -> Vec<(usize, usize)> {
+        self.positions(width, height, Cell::Wall)
+    }
+
+    /// Drain positions on a `width` by `height` map.
+    pub fn drains(&self, width: usize, height: usize) -> Vec<(usize, usize)> {
+        self.positions(width, height, Cell::Drain)
+    }
+
+    fn positions(&self, width: usize, height: usize, kind: Cell) -> Vec<(usize, usize)> {
+        let mut positions = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                if self.cell_at(x, y, width, height) == kind {
+                    positions.push((x, y));
+                }
+            }
+        }
+
+        positions
+    }
+
+    /// Emitters on a `width` by `height` map. Each emitter pixel stays a
+    /// single emitter however far the level is stretched, so rates don't
+    /// change with the map size.
+    pub fn emitters(&self, width: usize, height: usize) -> Vec<Emitter> {
+        let mut emitters = vec![];
+        if width == 0 || height == 0 {
+            return emitters;
+        }
+
+        for (i, cell) in self.cells.iter().enumerate() {
+            if let Cell::Emitter { material, rate } = *cell {
+                let (lx, ly) = (i % self.width, i / self.width);
+                emitters.push(Emitter {
+                    x: (lx * width / self.width).min(width - 1),
+                    y: (ly * height / self.height).min(height - 1),
+                    material,
+                    rate,
+                    carry: 0.0,
+                });
+            }
+        }
+
+        emitters
+    }
+}
+
+fn cell(pixel: [u8; 4]) -> Cell {
+    let [r, g, b, a] = pixel;
+    if a < 0x80 {
+        return Cell::Empty;
+    }
+
+    match [r, g, b] {
+        WALL => Cell::Wall,
+        DRAIN => Cell::Drain,
+        [rate, 0xff, number] => match Material::ALL.get(number as usize) {
+            Some(&material) if material != Material::Empty => Cell::Emitter {
+                material,
+                rate: rate as f32,
+            },
+            _ => Cell::Empty,
+        },
+        _ => Cell::Empty,
+    }
+}
+
+fn invalid<E: ToString>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAND: u8 = 1;
+
+    #[test]
+    fn decodes_colors() {
+        assert_eq!(cell([0xff, 0xff, 0xff, 0xff]), Cell::Wall);
+        assert_eq!(cell([0xff, 0x00, 0x00, 0xff]), Cell::Drain);
+        assert_eq!(
+            cell([20, 0xff, SAND, 0xff]),
+            Cell::Emitter {
+                material: Material::ALL[SAND as usize],
+                rate: 20.0
+            }
+        );
+        assert_eq!(cell([0x12, 0x34, 0x56, 0xff]), Cell::Empty);
+        assert_eq!(cell([0xff, 0xff, 0xff, 0x00]), Cell::Empty);
+        // Green without a material is just a color.
+        assert_eq!(cell([0x00, 0xff, 0x00, 0xff]), Cell::Empty);
+        assert_eq!(cell([0xff, 0xff, 0x00, 0xff]), Cell::Empty);
+        assert_eq!(cell([0x00, 0xff, 0xff, 0xff]), Cell::Empty);
+    }
+
+    #[test]
+    fn stretches_over_the_map() {
+        const W: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+        const D: [u8; 4] = [0xff, 0x00, 0x00, 0xff];
+        const E: [u8; 4] = [0, 0, 0, 0];
+        let level = Level::from_pixels(2, &[W, E, E, D]);
+
+        assert_eq!(level.walls(2, 2), [(0, 0)]);
+        assert_eq!(level.walls(4, 2), [(0, 0), (1, 0)]);
+        assert_eq!(level.drains(4, 4), [(2, 2), (3, 2), (2, 3), (3, 3)]);
+        assert_eq!(level.walls(0, 4), []);
+        assert_eq!(level.drains(4, 0), []);
+    }
+
+    #[test]
+    fn emitters_stay_single() {
+        const E: [u8; 4] = [0, 0, 0, 0];
+        let level = Level::from_pixels(3, &[E, E, E, E, E, [9, 0xff, SAND, 0xff]]);
+
+        let at = |width, height| {
+            let emitters = level.emitters(width, height);
+            assert_eq!(emitters.len(), 1);
+            assert_eq!(emitters[0].rate, 9.0);
+            (emitters[0].x, emitters[0].y)
+        };
+        assert_eq!(at(3, 2), (2, 1));
+        assert_eq!(at(30, 20), (20, 10));
+        // Shrunk below the level's size, emitters stay on the map.
+        assert_eq!(at(1, 1), (0, 0));
+
+        assert!(level.emitters(0, 2).is_empty());
+        assert!(level.emitters(3, 0).is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+/// How a material moves.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Phase {
+    /// Doesn't move, and nothing moves through it.
+    Static,
+    /// Falls and piles up.
+    Powder,
+    /// Falls and spreads sideways.
+    Liquid,
+    /// Rises and spreads sideways.
+    Gas,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Material {
+    Empty,
+    Sand,
+    Water,
+    Stone,
+    Fire,
+    Smoke,
+    Steam,
+    Oil,
+    Lava,
+}
+
+impl Material {
+    pub const ALL: [Material; 9] = [
+        Material::Empty,
+        Material::Sand,
+        Material::Water,
+        Material::Stone,
+        Material::Fire,
+        Material::Smoke,
+        Material::Steam,
+        Material::Oil,
+        Material::Lava,
+    ];
+
+    pub fn phase(self) -> Phase {
+        match self {
+            Material::Empty | Material::Fire | Material::Smoke | Material::Steam => Phase::Gas,
+            Material::Sand => Phase::Powder,
+            Material::Water | Material::Oil | Material::Lava => Phase::Liquid,
+            Material::Stone => Phase::Static,
+        }
+    }
+
+    /// Relative density. Heavier materials sink through lighter ones that
+    /// aren't static or powder.
+    pub fn density(self) -> f32 {
+        match self {
+            Material::Empty => 0.0,
+            Material::Fire => 0.2,
+            Material::Smoke => 0.3,
+            Material::Steam => 0.3,
+            Material::Oil => 0.8,
+            Material::Water => 1.0,
+            Material::Sand => 1.6,
+            Material::Lava => 2.5,
+            Material::Stone => f32::INFINITY,
+        }
+    }
+
+    /// Chance per tick that a fluid tries to move at all, so lava crawls
+    /// while water runs.
+    pub fn mobility(self) -> f32 {
+        match self {
+            Material::Lava => 0.25,
+            Material::Oil => 0.8,
+            _ => 1.0,
+        }
+    }
+
+    /// Number of ticks a short-lived material lasts on average, if it's
+    /// short-lived at all.
+    pub fn lifetime(self) -> Option<u16> {
+        match self {
+            Material::Fire => Some(30),
+            Material::Smoke => Some(90),
+            Material::Steam => Some(120),
+            _ => None,
+        }
+    }
+
+    pub fn is_flammable(self) -> bool {
+        self == Material::Oil
+    }
+
+    /// Base color, before per-grain variation.
+    pub fn color(self) -> (f32, f32, f32) {
+        match self {
+            Material::Empty => (0.0, 0.0, 0.0),
+            Material::Sand => (0.0, 0.9, 0.7),
+            Material::Water => (0.1, 0.3, 0.9),
+            Material::Stone => (0.4, 0.4, 0.45),
+            Material::Fire => (1.0, 0.45, 0.05),
+            Material::Smoke => (0.25, 0.25, 0.25),
+            Material::Steam => (0.6, 0.65, 0.7),
+            Material::Oil => (0.35, 0.2, 0.05),
+            Material::Lava => (0.95, 0.2, 0.0),
+        }
+    }
+}
@@ -0,0 +1,358 @@
+mod hourglass;
+mod level;
+mod material;
+mod render;
+mod world;
+
+use rand::Rng;
+
+use crate::control::Event;
+use crate::gradient::Palette;
+use crate::params::{palette_from_param, palette_param, Param};
+use crate::{Canvas, FrameTick, Scene};
+
+pub use hourglass::HourglassScene;
+use level::Emitter;
+pub use level::Level;
+use material::{Material, Phase};
+use render::RenderMode;
+pub use world::Metrics;
+use world::{Tile, World};
+
+pub struct SandScene {
+    world: World,
+
+    /// Center of the spout, as a fraction of the canvas width.
+    spout_x: f32,
+    /// Width of the spout, as a fraction of the canvas width.
+    spout_width: f32,
+    /// Grains poured per second.
+    spout_rate: f32,
+    /// Grains owed from previous frames, so low rates still pour.
+    spout_carry: f32,
+    /// What the spout pours.
+    spout_material: Material,
+
+    color: (f32, f32, f32),
+
+    /// Stretched over the canvas again whenever it's resized.
+    level: Option<Level>,
+    /// Where the level's walls were built, so a resize takes down only
+    /// those and not stone made since.
+    walls: Vec<(usize, usize)>,
+    emitters: Vec<Emitter>,
+    /// Spots that remove whatever lands on them.
+    drains: Vec<(usize, usize)>,
+
+    render: RenderMode,
+    /// Gradient for the heatmap render modes, if not the default one.
+    palette: Option<Palette>,
+    palette_index: usize,
+    heatmap: Palette,
+}
+
+impl SandScene {
+    pub fn new(canvas: &Canvas) -> Self {
+        SandScene {
+            world: World::new(canvas.width as usize, canvas.height as usize),
+            spout_x: 0.5,
+            spout_width: 0.625,
+            spout_rate: 75.0,
+            spout_carry: 0.0,
+            spout_material: Material::Sand,
+            color: Material::Sand.color(),
+            level: None,
+            walls: vec![],
+            emitters: vec![],
+            drains: vec![],
+            render: RenderMode::Flat,
+            palette: None,
+            palette_index: 0,
+            heatmap: render::heatmap(),
+        }
+    }
+
+    /// Builds `level`'s walls, drains and emitters, stretched over the
+    /// canvas. The spout is turned off, since the level has its own.
+    pub fn with_level(mut self, level: &Level) -> Self {
+        self.level = Some(level.clone());
+        self.build_level();
+        self.spout_rate = 0.0;
+        self
+    }
+
+    fn build_level(&mut self) {
+        let level = match &self.level {
+            Some(level) => level,
+            None => return,
+        };
+        let mut rng = rand::thread_rng();
+        let (width, height) = (self.world.width(), self.world.height());
+
+        self.walls = level.walls(width, height);
+        for &(x, y) in &self.walls {
+            self.world
+                .place(x as i32, y as i32, Material::Stone, &mut rng);
+        }
+
+        self.drains = level.drains(width, height);
+        self.emitters = level.emitters(width, height);
+    }
+
+    /// What the simulation has done so far.
+    pub fn metrics(&self) -> Metrics {
+        self.world.metrics()
+    }
+
+    /// Empties the world, leaving only the level.
+    fn clear(&mut self) {
+        let (x, y) = self.world.gravity();
+        self.world = World::new(self.world.width(), self.world.height());
+        self.world.set_gravity(x, y);
+        self.build_level();
+    }
+
+    fn pour(&mut self, dt: f32) {
+        let mut rng = rand::thread_rng();
+        if self.world.map().is_empty() {
+            return;
+        }
+        let width = self.world.width() as f32;
+
+        self.spout_carry += self.spout_rate * dt;
+        let grains = self.spout_carry as u32;
+        self.spout_carry -= grains as f32;
+
+        let half_width = (self.spout_width * width / 2.0).max(0.5);
+        let center = self.spout_x * width;
+        let min = (center - half_width).clamp(0.0, width - 1.0) as usize;
+        let max = (center + half_width).clamp(0.0, width - 1.0) as usize;
+
+        for _ in 0..grains {
+            let x = rng.gen_range(min..=max);
+            if self.world.map()[(x, 0)].is_empty() {
+                self.world.place(x as i32, 0, self.spout_material, &mut rng);
+            }
+        }
+    }
+
+    fn emit(&mut self, dt: f32) {
+        let mut rng = rand::thread_rng();
+
+        for emitter in self.emitters.iter_mut() {
+            emitter.carry += emitter.rate * dt;
+
+            while emitter.carry >= 1.0 {
+                emitter.carry -= 1.0;
+
+                let (x, y) = (emitter.x, emitter.y);
+                if self.world.map()[(x, y)].is_empty() {
+                    self.world
+                        .place(x as i32, y as i32, emitter.material, &mut rng);
+                }
+            }
+        }
+    }
+
+    fn drain(&mut self) {
+        let mut rng = rand::thread_rng();
+
+        for &(x, y) in self.drains.iter() {
+            let tile = self.world.map()[(x, y)];
+            if !tile.is_empty() && tile.material.phase() != Phase::Static {
+                self.world
+                    .place(x as i32, y as i32, Material::Empty, &mut rng);
+            }
+        }
+    }
+
+    fn tile_color(&self, tile: &Tile) -> (f32, f32, f32) {
+        let color = match tile.material {
+            Material::Sand => self.color,
+            material => material.color(),
+        };
+
+        // Fire cools as it burns out.
+        let fade = match tile.material.lifetime() {
+            Some(lifetime) if tile.material == Material::Fire => {
+                1.0 - (self.world.age(tile) as f32 / lifetime as f32).min(1.0) * 0.6
+            }
+            _ => 1.0,
+        };
+
+        let (r, g, b) = render::shaded(color, tile);
+        (r * fade, g * fade, b * fade)
+    }
+
+    fn draw(&self, canvas: &mut Canvas) {
+        let heatmap = self.palette.as_ref().unwrap_or(&self.heatmap);
+        let height = self.world.height();
+
+        for (y, row) in self.world.map().rows().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                if tile.is_empty() {
+                    canvas.set_pixel(x as u32, y as u32, 0.0, 0.0, 0.0);
+                    continue;
+                }
+
+                let (r, g, b) = match self.render {
+                    RenderMode::Flat => self.tile_color(tile),
+                    RenderMode::Pressure => heatmap.sample(render::pressure_value(tile, height)),
+                    RenderMode::Age => heatmap.sample(render::age_value(&self.world, tile)),
+                    RenderMode::Velocity => {
+                        render::velocity_color(tile).unwrap_or_else(|| {
+                            // Resting tiles are dimmed so moving ones stand out.
+                            let (r, g, b) = self.tile_color(tile);
+                            (r * 0.2, g * 0.2, b * 0.2)
+                        })
+                    }
+                };
+                canvas.set_pixel(x as u32, y as u32, r, g, b);
+            }
+        }
+    }
+}
+
+impl Scene for SandScene {
+    fn name(&self) -> &str {
+        "sand"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        let material = Material::ALL
+            .iter()
+            .position(|&material| material == self.spout_material)
+            .unwrap();
+
+        vec![
+            Param::new("spout_x", 0.0, 1.0, self.spout_x),
+            Param::new("spout_width", 0.0, 1.0, self.spout_width),
+            Param::new("spout_rate", 0.0, 300.0, self.spout_rate),
+            Param::new(
+                "material",
+                1.0,
+                (Material::ALL.len() - 1) as f32,
+                material as f32,
+            ),
+            Param::new("red", 0.0, 1.0, self.color.0),
+            Param::new("green", 0.0, 1.0, self.color.1),
+            Param::new("blue", 0.0, 1.0, self.color.2),
+            Param::new(
+                "render",
+                0.0,
+                (RenderMode::ALL.len() - 1) as f32,
+                RenderMode::ALL
+                    .iter()
+                    .position(|&mode| mode == self.render)
+                    .unwrap() as f32,
+            ),
+            palette_param(self.palette_index),
+            Param::new("gravity_x", -1.0, 1.0, self.world.gravity().0),
+            Param::new("gravity_y", -1.0, 1.0, self.world.gravity().1),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        match name {
+            "spout_x" => self.spout_x = value,
+            "spout_width" => self.spout_width = value,
+            "spout_rate" => self.spout_rate = value,
+            "material" => {
+                let index = (value.round() as usize).clamp(1, Material::ALL.len() - 1);
+                self.spout_material = Material::ALL[index];
+            }
+            "red" => self.color.0 = value,
+            "green" => self.color.1 = value,
+            "blue" => self.color.2 = value,
+            "render" => {
+                let index = (value.round().max(0.0) as usize).min(RenderMode::ALL.len() - 1);
+                self.render = RenderMode::ALL[index];
+            }
+            "palette" => {
+                let (index, palette) = palette_from_param(value);
+                self.palette_index = index;
+                self.palette = palette;
+            }
+            "gravity_x" => {
+                let (_, y) = self.world.gravity();
+                self.world.set_gravity(value, y);
+            }
+            "gravity_y" => {
+                let (x, _) = self.world.gravity();
+                self.world.set_gravity(x, value);
+            }
+            _ => {}
+        }
+    }
+
+    /// Keeps the grains, settled from the bottom center of the new canvas.
+    /// A level's walls are built again at the new size instead of moved.
+    fn on_resize(&mut self, width: u32, height: u32) {
+        let mut rng = rand::thread_rng();
+        for &(x, y) in &self.walls {
+            if self.world.map()[(x, y)].material == Material::Stone {
+                self.world
+                    .place(x as i32, y as i32, Material::Empty, &mut rng);
+            }
+        }
+
+        self.world = self.world.resized(width as usize, height as usize);
+        self.build_level();
+    }
+
+    /// `clear` empties the world.
+    fn on_event(&mut self, event: &Event) {
+        if event.name == "clear" {
+            self.clear();
+        }
+    }
+
+    fn tick(&mut self, canvas: &mut Canvas, tick: &FrameTick) {
+        let mut rng = rand::thread_rng();
+
+        self.pour(tick.dt);
+        self.emit(tick.dt);
+        self.drain();
+
+        self.world.step(&mut rng);
+
+        self.draw(canvas);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stones(scene: &SandScene) -> usize {
+        scene
+            .world
+            .map()
+            .iter()
+            .filter(|tile| tile.material == Material::Stone)
+            .count()
+    }
+
+    #[test]
+    fn keeps_formed_stone_when_resized() {
+        const WALL: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+        const EMPTY: [u8; 4] = [0, 0, 0, 0];
+        let level = Level::from_pixels(2, &[WALL, EMPTY, EMPTY, EMPTY]);
+
+        let mut canvas = Canvas::new(8, 8);
+        let mut scene = SandScene::new(&canvas).with_level(&level);
+        let walls = stones(&scene);
+        assert_eq!(walls, 16);
+
+        // Lava touching water turns to stone.
+        let mut rng = rand::thread_rng();
+        scene.world.place(6, 7, Material::Lava, &mut rng);
+        scene.world.place(7, 7, Material::Water, &mut rng);
+        scene.tick(&mut canvas, &FrameTick::from_start());
+        let formed = stones(&scene) - walls;
+        assert!(formed > 0);
+
+        scene.on_resize(12, 12);
+        assert_eq!(stones(&scene), level.walls(12, 12).len() + formed);
+    }
+}
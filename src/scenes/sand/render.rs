@@ -0,0 +1,78 @@
+use palette::{FromColor, Hsv, Srgb};
+
+use crate::gradient::{Gradient, Palette};
+
+use super::world::{Tile, World, PRESSURE_WEIGHT};
+
+/// Steps it takes a tile to reach the end of the gradient in the age view,
+/// about half a minute.
+const AGE_SPAN: u32 = 900;
+
+/// What the sand scene shows for each tile.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RenderMode {
+    /// Each material in its own color.
+    Flat,
+    /// Powder pressure through a heatmap, for tuning the pressure rules.
+    Pressure,
+    /// Time since each tile was created, through a heatmap.
+    Age,
+    /// Direction each tile moved in last step, as a hue.
+    Velocity,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 4] = [
+        RenderMode::Flat,
+        RenderMode::Pressure,
+        RenderMode::Age,
+        RenderMode::Velocity,
+    ];
+}
+
+/// Heatmap used for the pressure and age views when no palette is chosen.
+pub fn heatmap() -> Palette {
+    Palette::from_gradient(&Gradient::new(&[
+        (0.0, Srgb::new(0.0, 0.0, 0.25)),
+        (0.25, Srgb::new(0.0, 0.3, 1.0)),
+        (0.5, Srgb::new(0.0, 0.9, 0.4)),
+        (0.75, Srgb::new(1.0, 0.9, 0.0)),
+        (1.0, Srgb::new(1.0, 0.1, 0.0)),
+    ]))
+}
+
+/// `color` with `tile`'s own slight variation, so grains don't all look
+/// alike.
+pub fn shaded((r, g, b): (f32, f32, f32), tile: &Tile) -> (f32, f32, f32) {
+    let shade = 0.85 + tile.shade as f32 / 255.0 * 0.3;
+    (
+        (r * shade).clamp(0.0, 1.0),
+        (g * shade).clamp(0.0, 1.0),
+        (b * shade).clamp(0.0, 1.0),
+    )
+}
+
+/// Where `tile`'s pressure falls on the heatmap. Pressure grows by
+/// [`PRESSURE_WEIGHT`] for each grain stacked above, so it's shown on a log
+/// scale up to a full column of `height` grains.
+pub fn pressure_value(tile: &Tile, height: usize) -> f32 {
+    let max = (PRESSURE_WEIGHT * height as f32).ln_1p();
+    (tile.pressure.max(0.0).ln_1p() / max).min(1.0)
+}
+
+pub fn age_value(world: &World, tile: &Tile) -> f32 {
+    (world.age(tile) as f32 / AGE_SPAN as f32).min(1.0)
+}
+
+/// Color for the direction `tile` moved in, or `None` if it stayed put.
+pub fn velocity_color(tile: &Tile) -> Option<(f32, f32, f32)> {
+    let (dx, dy) = tile.velocity;
+    if (dx, dy) == (0, 0) {
+        return None;
+    }
+
+    let hue = (dy as f32).atan2(dx as f32).to_degrees();
+    let rgb = Srgb::from_color(Hsv::new(hue, 1.0, 1.0));
+
+    Some((rgb.red, rgb.green, rgb.blue))
+}
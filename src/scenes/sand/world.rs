@@ -0,0 +1,716 @@
+use rand::{prelude::SliceRandom, Rng};
+
+use crate::grid::{Grid, Neighborhood};
+
+use super::material::{Material, Phase};
+
+/// Pressure a powder grain gets from each grain resting on it.
+pub const PRESSURE_WEIGHT: f32 = 100000.0;
+/// Pressure a grain keeps for itself, and needs to exceed to flow sideways.
+const PRESSURE_THRESHOLD: f32 = 0.1;
+
+/// Gravity weaker than this, in g, leaves everything floating.
+const MIN_GRAVITY: f32 = 0.05;
+
+/// Neighbor offsets, an eighth of a turn apart, clockwise from the right with
+/// y going down the map.
+const DIRECTIONS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+/// Side length of the square chunks the map is split into. Chunks where
+/// nothing changed last step are skipped.
+const CHUNK_SIZE: usize = 16;
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct Tile {
+    pub material: Material,
+    pub pressure: f32,
+    /// Per-grain color variation.
+    pub shade: u8,
+    /// Step the tile was created on.
+    pub born: u32,
+    /// Direction the tile moved in last step, zero if it stayed put.
+    pub velocity: (i8, i8),
+    /// Whether the tile already moved this step.
+    moved: bool,
+}
+
+pub const EMPTY_TILE: Tile = Tile {
+    material: Material::Empty,
+    pressure: 0.0,
+    shade: 128,
+    born: 0,
+    velocity: (0, 0),
+    moved: false,
+};
+
+impl Tile {
+    pub fn is_empty(&self) -> bool {
+        self.material == Material::Empty
+    }
+
+    /// Whether this tile can move into `target`'s place, swapping the two.
+    /// `along` is the direction of the move along gravity: positive is down.
+    fn can_displace(&self, target: &Tile, along: i32) -> bool {
+        if target.is_empty() {
+            return true;
+        }
+
+        match (self.material.phase(), target.material.phase()) {
+            (Phase::Static, _) | (_, Phase::Static) | (_, Phase::Powder) => false,
+            _ => {
+                let (mover, other) = (self.material.density(), target.material.density());
+                (along > 0 && mover > other) || (along < 0 && mover < other)
+            }
+        }
+    }
+}
+
+/// Running totals of what the simulation has done, since the world was
+/// created.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Metrics {
+    /// Non-empty tiles placed, by reactions or from outside.
+    pub created: u64,
+    /// Non-empty tiles replaced by `place`.
+    pub destroyed: u64,
+    /// Tiles moved by a step.
+    pub moves: u64,
+}
+
+impl Metrics {
+    /// Number of non-empty tiles there should be. Steps only ever swap tiles,
+    /// so anything else means a grain was lost or duplicated.
+    pub fn grains(&self) -> u64 {
+        self.created - self.destroyed
+    }
+}
+
+/// The falling-sand simulation.
+pub struct World {
+    map: Grid<Tile>,
+    /// Number of steps taken so far.
+    frame: u32,
+    metrics: Metrics,
+
+    /// Gravity in g, with y going down the map.
+    gravity: (f32, f32),
+    /// Index into [`DIRECTIONS`] closest to gravity, if there's any gravity.
+    down: Option<usize>,
+
+    chunks_x: usize,
+    chunks_y: usize,
+    /// Chunks to update this step.
+    dirty: Vec<bool>,
+    /// Chunks to update next step, woken by changes during this one.
+    next_dirty: Vec<bool>,
+
+    /// Cells of the dirty chunks in scan order, kept to reuse the allocation.
+    scan: Vec<(i32, i32)>,
+}
+
+impl World {
+    pub fn new(width: usize, height: usize) -> Self {
+        let chunks_x = width.div_ceil(CHUNK_SIZE);
+        let chunks_y = height.div_ceil(CHUNK_SIZE);
+
+        World {
+            map: Grid::new(width, height, EMPTY_TILE),
+            frame: 0,
+            metrics: Metrics::default(),
+            gravity: (0.0, 1.0),
+            down: Some(2),
+            chunks_x,
+            chunks_y,
+            dirty: vec![true; chunks_x * chunks_y],
+            next_dirty: vec![true; chunks_x * chunks_y],
+            scan: Vec::with_capacity(width * height),
+        }
+    }
+
+    /// A copy on a `width` by `height` map, with the same gravity. Tiles keep
+    /// their place from the bottom center, and those that no longer fit are
+    /// dropped.
+    pub fn resized(&self, width: usize, height: usize) -> World {
+        let mut world = World::new(width, height);
+        world.frame = self.frame;
+        world.set_gravity(self.gravity.0, self.gravity.1);
+
+        let dx = (width as i32 - self.width() as i32) / 2;
+        let dy = height as i32 - self.height() as i32;
+        for (y, row) in self.map.rows().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let (to_x, to_y) = (x as i32 + dx, y as i32 + dy);
+                if !tile.is_empty() && world.map.in_bounds(to_x, to_y) {
+                    world.map[(to_x as usize, to_y as usize)] = *tile;
+                    world.metrics.created += 1;
+                }
+            }
+        }
+
+        world
+    }
+
+    pub fn width(&self) -> usize {
+        self.map.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.map.height()
+    }
+
+    pub fn map(&self) -> &Grid<Tile> {
+        &self.map
+    }
+
+    /// Number of steps since `tile` was created.
+    pub fn age(&self, tile: &Tile) -> u32 {
+        self.frame.wrapping_sub(tile.born)
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.metrics
+    }
+
+    /// Number of non-empty tiles.
+    pub fn grains(&self) -> u64 {
+        self.map.iter().filter(|tile| !tile.is_empty()).count() as u64
+    }
+
+    pub fn gravity(&self) -> (f32, f32) {
+        self.gravity
+    }
+
+    /// Sets gravity, in g with y going down the map. Grains fall along the
+    /// neighbor direction closest to it, and more slowly when it's weaker
+    /// than 1 g. The whole map is woken, so settled piles slide.
+    pub fn set_gravity(&mut self, x: f32, y: f32) {
+        if (x, y) == self.gravity {
+            return;
+        }
+
+        self.gravity = (x, y);
+        self.down = if x.hypot(y) < MIN_GRAVITY {
+            None
+        } else {
+            let turns = (y.atan2(x) / std::f32::consts::FRAC_PI_4).round() as i32;
+            Some(turns.rem_euclid(8) as usize)
+        };
+
+        self.unsettle();
+    }
+
+    /// Mirrors the map top to bottom, as when turning it over.
+    pub fn flip_vertical(&mut self) {
+        self.map.flip_vertical();
+        self.unsettle();
+    }
+
+    /// Wakes the whole map after it was turned around. Pressure built up
+    /// along the old gravity is dropped, since it would push grains the
+    /// wrong way.
+    fn unsettle(&mut self) {
+        for tile in self.map.iter_mut() {
+            tile.pressure = 0.0;
+        }
+        for dirty in self.next_dirty.iter_mut() {
+            *dirty = true;
+        }
+    }
+
+    /// Neighbor offset `turns` eighths of a turn clockwise from down.
+    fn dir(&self, down: usize, turns: i32) -> (i32, i32) {
+        DIRECTIONS[(down as i32 + turns).rem_euclid(8) as usize]
+    }
+
+    /// Replaces the tile at (`x`, `y`) with a new tile of `material`.
+    pub fn place<R: Rng>(&mut self, x: i32, y: i32, material: Material, rng: &mut R) {
+        let tile = Tile {
+            material,
+            shade: rng.gen(),
+            born: self.frame,
+            ..EMPTY_TILE
+        };
+
+        if !self.map[(x as usize, y as usize)].is_empty() {
+            self.metrics.destroyed += 1;
+        }
+        if !tile.is_empty() {
+            self.metrics.created += 1;
+        }
+
+        self.map[(x as usize, y as usize)] = tile;
+        self.wake(x, y);
+    }
+
+    /// Swaps the tiles at two places, as when moving a grain by hand.
+    pub fn swap(&mut self, (x, y): (i32, i32), (to_x, to_y): (i32, i32)) {
+        let tile = self.map[(x as usize, y as usize)];
+        let target = self.map[(to_x as usize, to_y as usize)];
+
+        self.map[(to_x as usize, to_y as usize)] = tile;
+        self.map[(x as usize, y as usize)] = target;
+        self.wake(x, y);
+        self.wake(to_x, to_y);
+    }
+
+    /// Marks the chunks around (`x`, `y`) for updating next step. Neighboring
+    /// chunks are included since tiles on a chunk's edge can move into them.
+    fn wake(&mut self, x: i32, y: i32) {
+        if self.chunks_x == 0 || self.chunks_y == 0 {
+            return;
+        }
+
+        let chunk = |v: i32, count: usize| (v.max(0) as usize / CHUNK_SIZE).min(count - 1);
+
+        for cy in chunk(y - 1, self.chunks_y)..=chunk(y + 1, self.chunks_y) {
+            for cx in chunk(x - 1, self.chunks_x)..=chunk(x + 1, self.chunks_x) {
+                self.next_dirty[cy * self.chunks_x + cx] = true;
+            }
+        }
+    }
+
+    /// Collects the cells of dirty chunks, starting from the side gravity
+    /// points at. Rows across gravity alternate direction each step so
+    /// nothing drifts to one side.
+    fn collect_scan(&mut self) {
+        self.scan.clear();
+
+        let width = self.width();
+        let height = self.height();
+        let (down_x, down_y) = match self.down {
+            Some(down) => self.dir(down, 0),
+            None => (0, 0),
+        };
+        let reverse = match down_x {
+            0 => self.frame % 2 == 1,
+            down_x => down_x > 0,
+        };
+
+        for i in 0..height {
+            let y = if down_y >= 0 { height - 1 - i } else { i };
+            let cy = y / CHUNK_SIZE;
+
+            for i in 0..self.chunks_x {
+                let cx = if reverse { self.chunks_x - 1 - i } else { i };
+                if !self.dirty[cy * self.chunks_x + cx] {
+                    continue;
+                }
+
+                let xs = cx * CHUNK_SIZE..((cx + 1) * CHUNK_SIZE).min(width);
+                if reverse {
+                    self.scan.extend(xs.rev().map(|x| (x as i32, y as i32)));
+                } else {
+                    self.scan.extend(xs.map(|x| (x as i32, y as i32)));
+                }
+            }
+        }
+    }
+
+    pub fn step<R: Rng>(&mut self, rng: &mut R) {
+        self.frame = self.frame.wrapping_add(1);
+        std::mem::swap(&mut self.dirty, &mut self.next_dirty);
+        for dirty in self.next_dirty.iter_mut() {
+            *dirty = false;
+        }
+
+        self.collect_scan();
+        let scan = std::mem::take(&mut self.scan);
+
+        for &(x, y) in scan.iter() {
+            let tile = &mut self.map[(x as usize, y as usize)];
+            tile.moved = false;
+            tile.velocity = (0, 0);
+        }
+
+        for &(x, y) in scan.iter() {
+            let tile = self.map[(x as usize, y as usize)];
+            if tile.is_empty() || tile.moved {
+                continue;
+            }
+
+            if !self.react(x, y, rng) {
+                self.move_tile(x, y, rng);
+            }
+        }
+
+        let down = match self.down {
+            Some(down) => down,
+            None => {
+                self.scan = scan;
+                return;
+            }
+        };
+
+        let (up_x, up_y) = self.dir(down, 4);
+        let (left_x, left_y) = self.dir(down, 2);
+        let (right_x, right_y) = self.dir(down, -2);
+
+        for &(x, y) in scan.iter() {
+            let tile = self.map[(x as usize, y as usize)];
+            if tile.material.phase() != Phase::Powder {
+                continue;
+            }
+
+            if let Some(above) = self.map.get(x + up_x, y + up_y) {
+                if above.material.phase() == Phase::Powder {
+                    self.map[(x as usize, y as usize)].pressure = PRESSURE_WEIGHT + above.pressure;
+                }
+            }
+        }
+
+        for &(x, y) in scan.iter() {
+            let tile = self.map[(x as usize, y as usize)];
+            if tile.material.phase() != Phase::Powder || tile.pressure <= PRESSURE_THRESHOLD {
+                continue;
+            }
+
+            let is_powder = |tile: Option<Tile>| {
+                tile.is_some_and(|tile| tile.material.phase() == Phase::Powder)
+            };
+            let left = is_powder(self.map.get(x + left_x, y + left_y));
+            let right = is_powder(self.map.get(x + right_x, y + right_y));
+            let pressure_over = tile.pressure - PRESSURE_THRESHOLD;
+
+            let mut push = |dx: i32, dy: i32, pressure: f32| {
+                self.map[((x + dx) as usize, (y + dy) as usize)].pressure += pressure;
+            };
+
+            if left && right {
+                push(left_x, left_y, pressure_over / 2.0);
+                push(right_x, right_y, pressure_over / 2.0);
+            } else if left {
+                push(left_x, left_y, pressure_over);
+            } else if right {
+                push(right_x, right_y, pressure_over);
+            }
+
+            self.map[(x as usize, y as usize)].pressure -= pressure_over;
+        }
+
+        for &(x, y) in scan.iter() {
+            let tile = self.map[(x as usize, y as usize)];
+            if tile.moved
+                || tile.material.phase() != Phase::Powder
+                || tile.pressure < PRESSURE_THRESHOLD
+            {
+                continue;
+            }
+
+            let mut sides = [(left_x, left_y), (right_x, right_y)];
+            sides.shuffle(rng);
+
+            for (dx, dy) in sides {
+                if self.try_move(x, y, dx, dy) {
+                    let moved = &mut self.map[((x + dx) as usize, (y + dy) as usize)];
+                    moved.pressure = tile.pressure - PRESSURE_THRESHOLD;
+                    break;
+                }
+            }
+        }
+
+        self.scan = scan;
+
+        debug_assert_eq!(
+            self.grains(),
+            self.metrics.grains(),
+            "sand step lost or duplicated grains"
+        );
+    }
+
+    /// Lets the tile at (`x`, `y`) react with its neighbors, returning
+    /// whether the tile itself was replaced.
+    fn react<R: Rng>(&mut self, x: i32, y: i32, rng: &mut R) -> bool {
+        let tile = self.map[(x as usize, y as usize)];
+
+        if let Some(lifetime) = tile.material.lifetime() {
+            // Short-lived tiles change eventually even when they can't move.
+            self.wake(x, y);
+
+            if rng.gen_bool(1.0 / lifetime as f64) {
+                let next = match tile.material {
+                    Material::Fire if rng.gen_bool(0.5) => Material::Smoke,
+                    Material::Steam if rng.gen_bool(0.2) => Material::Water,
+                    _ => Material::Empty,
+                };
+                self.place(x, y, next, rng);
+                return true;
+            }
+        }
+
+        for (dx, dy) in Neighborhood::VonNeumann(1).offsets() {
+            let neighbor = match self.map.get(x + dx, y + dy) {
+                Some(neighbor) => neighbor,
+                None => continue,
+            };
+
+            match (tile.material, neighbor.material) {
+                (Material::Fire, other) if other.is_flammable() && rng.gen_bool(0.3) => {
+                    self.place(x + dx, y + dy, Material::Fire, rng);
+                }
+                (Material::Fire, Material::Water) => {
+                    self.place(x, y, Material::Steam, rng);
+                    return true;
+                }
+                (Material::Lava, Material::Water) => {
+                    self.place(x, y, Material::Stone, rng);
+                    self.place(x + dx, y + dy, Material::Steam, rng);
+                    return true;
+                }
+                (Material::Lava, other) if other.is_flammable() && rng.gen_bool(0.2) => {
+                    self.place(x + dx, y + dy, Material::Fire, rng);
+                }
+                _ => {}
+            }
+        }
+
+        false
+    }
+
+    /// Moves the tile at (`x`, `y`) according to its phase.
+    fn move_tile<R: Rng>(&mut self, x: i32, y: i32, rng: &mut R) {
+        let down = match self.down {
+            Some(down) => down,
+            None => return,
+        };
+
+        let material = self.map[(x as usize, y as usize)].material;
+        let strength = self.gravity.0.hypot(self.gravity.1).min(1.0);
+        if !rng.gen_bool((material.mobility() * strength) as f64) {
+            // Slow fluids and weak gravity might still move things next step.
+            self.wake(x, y);
+            return;
+        }
+
+        // Moves in eighths of a turn from down, tried in order.
+        let side = if rng.gen() { 1 } else { -1 };
+        let turns: &[i32] = match material.phase() {
+            Phase::Static => &[],
+            Phase::Powder => &[0, side, -side],
+            Phase::Liquid => &[0, side, -side, 2 * side, -2 * side],
+            Phase::Gas => {
+                // Fire flickers rather than rising steadily.
+                if material == Material::Fire && rng.gen_bool(0.5) {
+                    &[]
+                } else {
+                    &[4, 4 + side, 4 - side, 2 * side, -2 * side]
+                }
+            }
+        };
+
+        for &turn in turns {
+            let (dx, dy) = self.dir(down, turn);
+            if self.try_move(x, y, dx, dy) {
+                let moved = &mut self.map[((x + dx) as usize, (y + dy) as usize)];
+                moved.pressure = 0.0;
+                return;
+            }
+        }
+    }
+
+    /// Swaps the tile at (`x`, `y`) with its neighbor at (`dx`, `dy`) if it
+    /// can displace it, marking both as moved.
+    fn try_move(&mut self, x: i32, y: i32, dx: i32, dy: i32) -> bool {
+        let tile = self.map[(x as usize, y as usize)];
+        let target = match self.map.get(x + dx, y + dy) {
+            Some(target) => target,
+            None => return false,
+        };
+
+        let (down_x, down_y) = match self.down {
+            Some(down) => self.dir(down, 0),
+            None => return false,
+        };
+        let along = dx * down_x + dy * down_y;
+
+        // Sideways moves only go into gaps, so fluids don't endlessly trade
+        // places in a layer.
+        let can_move = if along == 0 {
+            target.is_empty()
+                || (tile.material.phase() != Phase::Gas && target.material.phase() == Phase::Gas)
+        } else {
+            tile.can_displace(&target, along)
+        };
+
+        if can_move {
+            self.map[((x + dx) as usize, (y + dy) as usize)] = Tile {
+                velocity: (dx as i8, dy as i8),
+                moved: true,
+                ..tile
+            };
+            self.map[(x as usize, y as usize)] = Tile {
+                velocity: if target.is_empty() {
+                    (0, 0)
+                } else {
+                    (-dx as i8, -dy as i8)
+                },
+                moved: !target.is_empty(),
+                ..target
+            };
+            self.wake(x, y);
+            self.wake(x + dx, y + dy);
+            self.metrics.moves += 1;
+        }
+
+        can_move
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// Materials that never react or expire, so their counts can't change.
+    const INERT: [Material; 5] = [
+        Material::Empty,
+        Material::Sand,
+        Material::Water,
+        Material::Stone,
+        Material::Oil,
+    ];
+
+    const STEPS: usize = 200;
+
+    fn world_from(width: usize, height: usize, cells: &[Material], rng: &mut StdRng) -> World {
+        let mut world = World::new(width, height);
+        for (i, material) in cells.iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            world.place(x as i32, y as i32, *material, rng);
+        }
+
+        world
+    }
+
+    fn count(world: &World, material: Material) -> usize {
+        world
+            .map()
+            .iter()
+            .filter(|tile| tile.material == material)
+            .count()
+    }
+
+    /// A map size and a material for each of its cells.
+    fn cells(
+        materials: &'static [Material],
+    ) -> impl Strategy<Value = (usize, usize, Vec<Material>)> {
+        (1usize..48, 1usize..48).prop_flat_map(move |(width, height)| {
+            (
+                Just(width),
+                Just(height),
+                prop::collection::vec(prop::sample::select(materials), width * height),
+            )
+        })
+    }
+
+    #[test]
+    fn empty_worlds_step() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for (width, height) in [(0, 0), (0, 8), (8, 0)] {
+            let mut world = World::new(width, height);
+            world.set_gravity(1.0, 0.0);
+            world.step(&mut rng);
+            assert_eq!(world.grains(), 0);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn steps_conserve_grains((width, height, cells) in cells(&Material::ALL), seed: u64) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut world = world_from(width, height, &cells, &mut rng);
+
+            for _ in 0..STEPS {
+                world.step(&mut rng);
+                prop_assert_eq!(world.grains(), world.metrics().grains());
+            }
+        }
+
+        #[test]
+        fn steps_conserve_grains_under_any_gravity(
+            (width, height, cells) in cells(&Material::ALL),
+            gravity in (-1.0f32..1.0, -1.0f32..1.0),
+            seed: u64,
+        ) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut world = world_from(width, height, &cells, &mut rng);
+            world.set_gravity(gravity.0, gravity.1);
+
+            for _ in 0..STEPS {
+                world.step(&mut rng);
+                prop_assert_eq!(world.grains(), world.metrics().grains());
+            }
+        }
+
+        #[test]
+        fn steps_conserve_inert_materials((width, height, cells) in cells(&INERT), seed: u64) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut world = world_from(width, height, &cells, &mut rng);
+            let before: Vec<usize> = INERT.iter().map(|m| count(&world, *m)).collect();
+
+            for _ in 0..STEPS {
+                world.step(&mut rng);
+            }
+
+            let after: Vec<usize> = INERT.iter().map(|m| count(&world, *m)).collect();
+            prop_assert_eq!(before, after);
+        }
+
+        #[test]
+        fn steps_stay_in_bounds((width, height, cells) in cells(&Material::ALL), seed: u64) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut world = world_from(width, height, &cells, &mut rng);
+
+            for _ in 0..STEPS {
+                // Keep pouring along the edges, where neighbors fall outside
+                // the map.
+                let (right, bottom) = (width as i32 - 1, height as i32 - 1);
+                for (x, y) in [(0, 0), (right, 0), (0, bottom), (right, bottom)] {
+                    let material = Material::ALL[rng.gen_range(0..Material::ALL.len())];
+                    world.place(x, y, material, &mut rng);
+                }
+
+                world.step(&mut rng);
+            }
+
+            prop_assert_eq!(world.height(), height);
+            prop_assert_eq!(world.width(), width);
+            prop_assert_eq!(world.grains(), world.metrics().grains());
+        }
+
+        #[test]
+        fn resizes_keep_what_fits(
+            (width, height, cells) in cells(&Material::ALL),
+            (new_width, new_height) in (1usize..48, 1usize..48),
+            seed: u64,
+        ) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let world = world_from(width, height, &cells, &mut rng);
+            let mut resized = world.resized(new_width, new_height);
+
+            prop_assert_eq!(resized.width(), new_width);
+            prop_assert_eq!(resized.height(), new_height);
+            if new_width >= width && new_height >= height {
+                prop_assert_eq!(resized.grains(), world.grains());
+            }
+
+            for _ in 0..STEPS {
+                resized.step(&mut rng);
+                prop_assert_eq!(resized.grains(), resized.metrics().grains());
+            }
+        }
+    }
+}
@@ -0,0 +1,453 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, FLOAT, INT};
+
+use crate::control::Event;
+use crate::params::{knob_params, Param, KNOB_NAMES};
+use crate::watch::FileWatch;
+use crate::{Canvas, FrameTick, Scene};
+
+/// Operations a script may run per call before it's stopped, so an endless
+/// loop can't freeze the generator.
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// Color the canvas is filled with while the script is broken.
+const ERROR_COLOR: (f32, f32, f32) = (0.3, 0.0, 0.0);
+
+/// The canvas as scripts see it. Pixels are kept between frames, so scripts
+/// can draw over what they drew last.
+#[derive(Clone)]
+struct ScriptCanvas {
+    width: INT,
+    height: INT,
+    pixels: Rc<RefCell<Vec<[f32; 3]>>>,
+}
+
+impl ScriptCanvas {
+    fn new(width: u32, height: u32) -> Self {
+        ScriptCanvas {
+            width: width as INT,
+            height: height as INT,
+            pixels: Rc::new(RefCell::new(vec![[0.0; 3]; (width * height) as usize])),
+        }
+    }
+
+    /// Index of the pixel at (`x`, `y`), if it's on the canvas.
+    fn index(&self, x: &Dynamic, y: &Dynamic) -> Result<Option<usize>, Box<EvalAltResult>> {
+        let (x, y) = (number(x)?.floor() as INT, number(y)?.floor() as INT);
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return Ok(None);
+        }
+        Ok(Some((y * self.width + x) as usize))
+    }
+
+    /// Adds the canvas API to `engine`. Coordinates and colors can be
+    /// integers or floats, with colors in `0.0..=1.0`; pixels off the
+    /// canvas are ignored.
+    ///
+    /// ```text
+    /// canvas.width, canvas.height
+    /// canvas.set_pixel(x, y, r, g, b)
+    /// canvas.get_pixel(x, y)  // [r, g, b]
+    /// canvas.fill(r, g, b)
+    /// ```
+    fn register(engine: &mut Engine) {
+        engine
+            .register_type_with_name::<ScriptCanvas>("Canvas")
+            .register_get("width", |canvas: &mut ScriptCanvas| canvas.width)
+            .register_get("height", |canvas: &mut ScriptCanvas| canvas.height)
+            .register_fn(
+                "set_pixel",
+                |canvas: &mut ScriptCanvas,
+                 x: Dynamic,
+                 y: Dynamic,
+                 r: Dynamic,
+                 g: Dynamic,
+                 b: Dynamic|
+                 -> Result<(), Box<EvalAltResult>> {
+                    let color = [number(&r)?, number(&g)?, number(&b)?];
+                    if let Some(index) = canvas.index(&x, &y)? {
+                        canvas.pixels.borrow_mut()[index] = color;
+                    }
+                    Ok(())
+                },
+            )
+            .register_fn(
+                "get_pixel",
+                |canvas: &mut ScriptCanvas,
+                 x: Dynamic,
+                 y: Dynamic|
+                 -> Result<Array, Box<EvalAltResult>> {
+                    let color = match canvas.index(&x, &y)? {
+                        Some(index) => canvas.pixels.borrow()[index],
+                        None => [0.0; 3],
+                    };
+                    Ok(color.iter().map(|&c| Dynamic::from(c as FLOAT)).collect())
+                },
+            )
+            .register_fn(
+                "fill",
+                |canvas: &mut ScriptCanvas,
+                 r: Dynamic,
+                 g: Dynamic,
+                 b: Dynamic|
+                 -> Result<(), Box<EvalAltResult>> {
+                    let color = [number(&r)?, number(&g)?, number(&b)?];
+                    canvas.pixels.borrow_mut().fill(color);
+                    Ok(())
+                },
+            );
+    }
+}
+
+fn number(value: &Dynamic) -> Result<f32, Box<EvalAltResult>> {
+    if let Ok(value) = value.as_float() {
+        return Ok(value as f32);
+    }
+    value
+        .as_int()
+        .map(|value| value as f32)
+        .map_err(|found| format!("expected a number, found {}", found).into())
+}
+
+/// A scene written in [Rhai](https://rhai.rs), reloaded whenever its file
+/// changes.
+///
+/// The script defines `tick(canvas, tick)`, called every frame, and can
+/// define `init(width, height)`, called when it's loaded, and the hooks
+/// `enter()`, `exit()`, `resize(width, height)` and `event(name, value)`,
+/// called like [`Scene`]'s. All run with `this` bound to a map that's kept
+/// between calls, for the script's own state. `tick` is a map of `t`, `dt`,
+/// `level`, `beat`, `onset`, `bands`, `envelopes` (see
+/// [`crate::audio::AudioFrame`]) and the `p1`..`p4` parameters. `value` is
+/// `()` for events sent without one.
+///
+/// ```text
+/// fn init(width, height) {
+///     this.x = 0;
+/// }
+///
+/// fn tick(canvas, tick) {
+///     canvas.fill(0, 0, 0);
+///     this.x = (this.x + 1) % canvas.width;
+///     for y in 0..canvas.height {
+///         canvas.set_pixel(this.x, y, tick.p1, 1.0, tick.level);
+///     }
+/// }
+/// ```
+///
+/// When the script fails to compile or run, the error is printed, the
+/// canvas turns dark red and the script is left alone until it's fixed.
+pub struct ScriptScene {
+    watch: FileWatch,
+    engine: Engine,
+    /// Variables from the script's top level.
+    scope: Scope<'static>,
+    /// The compiled script, unless it's broken.
+    ast: Option<AST>,
+    /// `this` in the script's functions.
+    state: Dynamic,
+    canvas: ScriptCanvas,
+    knobs: [f32; 4],
+}
+
+impl ScriptScene {
+    pub fn load<P: AsRef<Path>>(path: P, canvas: &Canvas) -> io::Result<Self> {
+        // Checked here so a wrong path fails at startup, like other files.
+        fs::metadata(&path)?;
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| println!("script: {}", text));
+        ScriptCanvas::register(&mut engine);
+
+        let mut scene = ScriptScene {
+            watch: FileWatch::new(&path),
+            engine,
+            scope: Scope::new(),
+            ast: None,
+            state: Dynamic::UNIT,
+            canvas: ScriptCanvas::new(canvas.width, canvas.height),
+            knobs: [0.5; 4],
+        };
+        scene.reload();
+
+        Ok(scene)
+    }
+
+    /// Compiles the script and runs its top level and `init`.
+    fn reload(&mut self) {
+        self.ast = None;
+        self.scope.clear();
+        self.state = Dynamic::from_map(Map::new());
+        self.canvas.pixels.borrow_mut().fill([0.0; 3]);
+
+        let source = match fs::read_to_string(self.watch.path()) {
+            Ok(source) => source,
+            Err(e) => return self.fail(e),
+        };
+        let ast = match self.engine.compile(&source) {
+            Ok(ast) => ast,
+            Err(e) => return self.fail(e),
+        };
+
+        if !defines(&ast, "tick", 2) {
+            return self.fail("no `tick(canvas, tick)` function");
+        }
+
+        if let Err(e) = self.engine.run_ast_with_scope(&mut self.scope, &ast) {
+            return self.fail(e);
+        }
+        if defines(&ast, "init", 2) {
+            let size = (self.canvas.width, self.canvas.height);
+            if let Err(e) = self.call(&ast, "init", size) {
+                return self.fail(e);
+            }
+        }
+
+        self.ast = Some(ast);
+    }
+
+    /// Calls one of the optional hooks, if the script defines it.
+    fn hook(&mut self, name: &str, args: impl rhai::FuncArgs, params: usize) {
+        let ast = match self.ast.take() {
+            Some(ast) if defines(&ast, name, params) => ast,
+            ast => {
+                self.ast = ast;
+                return;
+            }
+        };
+
+        match self.call(&ast, name, args) {
+            Ok(_) => self.ast = Some(ast),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    fn call(
+        &mut self,
+        ast: &AST,
+        name: &str,
+        args: impl rhai::FuncArgs,
+    ) -> Result<Dynamic, Box<EvalAltResult>> {
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        self.engine
+            .call_fn_with_options(options, &mut self.scope, ast, name, args)
+    }
+
+    /// Reports an error and stops running the script until it's reloaded.
+    fn fail<E: Display>(&mut self, e: E) {
+        eprintln!("script: {}: {}", self.watch.path().display(), e);
+        self.ast = None;
+    }
+
+    fn tick_map(&self, tick: &FrameTick) -> Map {
+        let audio = &tick.audio;
+        let floats = |values: &[f32]| -> Array {
+            values.iter().map(|&v| Dynamic::from(v as FLOAT)).collect()
+        };
+
+        let mut map = Map::new();
+        map.insert("t".into(), (tick.t as FLOAT).into());
+        map.insert("dt".into(), (tick.dt as FLOAT).into());
+        map.insert("level".into(), (audio.level as FLOAT).into());
+        map.insert("beat".into(), audio.beat.into());
+        map.insert("onset".into(), audio.onset.into());
+        map.insert("bands".into(), floats(&audio.bands).into());
+        map.insert("envelopes".into(), floats(&audio.envelopes).into());
+        for (name, value) in KNOB_NAMES.iter().zip(&self.knobs) {
+            map.insert((*name).into(), (*value as FLOAT).into());
+        }
+        map
+    }
+}
+
+/// Fills `canvas` with [`ERROR_COLOR`].
+fn show_error(canvas: &mut Canvas) {
+    let (r, g, b) = ERROR_COLOR;
+    canvas.clear_with_color(r, g, b);
+}
+
+fn defines(ast: &AST, name: &str, params: usize) -> bool {
+    ast.iter_functions()
+        .any(|f| f.name == name && f.params.len() == params)
+}
+
+impl Scene for ScriptScene {
+    fn name(&self) -> &str {
+        "script"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        knob_params(&self.knobs)
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        if let Some(i) = KNOB_NAMES.iter().position(|knob| *knob == name) {
+            self.knobs[i] = value;
+        }
+    }
+
+    fn on_enter(&mut self) {
+        self.hook("enter", (), 0);
+    }
+
+    fn on_exit(&mut self) {
+        self.hook("exit", (), 0);
+    }
+
+    fn on_resize(&mut self, width: u32, height: u32) {
+        self.canvas = ScriptCanvas::new(width, height);
+        self.hook("resize", (width as INT, height as INT), 2);
+    }
+
+    fn on_event(&mut self, event: &Event) {
+        let value = event
+            .value
+            .map_or(Dynamic::UNIT, |value| (value as FLOAT).into());
+        self.hook("event", (event.name.clone(), value), 2);
+    }
+
+    fn tick(&mut self, canvas: &mut Canvas, tick: &FrameTick) {
+        if self.watch.changed(tick.dt) {
+            self.reload();
+            if self.ast.is_some() {
+                println!("script: reloaded {}", self.watch.path().display());
+            }
+        }
+
+        let ast = match self.ast.take() {
+            Some(ast) => ast,
+            None => return show_error(canvas),
+        };
+
+        let args = (self.canvas.clone(), self.tick_map(tick));
+        match self.call(&ast, "tick", args) {
+            Ok(_) => self.ast = Some(ast),
+            Err(e) => {
+                self.fail(e);
+                return show_error(canvas);
+            }
+        }
+
+        let pixels = self.canvas.pixels.borrow();
+        for y in 0..canvas.height {
+            for x in 0..canvas.width {
+                let [r, g, b] = pixels[(y * canvas.width + x) as usize];
+                canvas.set_pixel(x, y, r, g, b);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(name: &str, source: &str) -> (ScriptScene, Canvas) {
+        let path = std::env::temp_dir().join(format!("matryx_{}.rhai", name));
+        fs::write(&path, source).unwrap();
+
+        let canvas = Canvas::new(4, 2);
+        (ScriptScene::load(&path, &canvas).unwrap(), canvas)
+    }
+
+    #[test]
+    fn keeps_state_between_ticks() {
+        let (mut scene, mut canvas) = scene(
+            "state",
+            "fn init(width, height) { this.x = width - 1; }
+             fn tick(canvas, tick) {
+                 canvas.fill(0, 0, 0);
+                 canvas.set_pixel(this.x, 1, 1, tick.p1, 0.0);
+                 this.x -= 1;
+             }",
+        );
+        scene.set_param("p1", 0.2);
+
+        scene.tick(&mut canvas, &FrameTick::from_start());
+        assert_eq!(&canvas.pixels()[21..24], &[255, 51, 0]);
+
+        scene.tick(&mut canvas, &FrameTick::from_start());
+        assert_eq!(&canvas.pixels()[18..21], &[255, 51, 0]);
+        assert_eq!(&canvas.pixels()[21..24], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn calls_hooks() {
+        let (mut scene, mut canvas) = scene(
+            "hooks",
+            "fn init(width, height) { this.x = 0; this.red = 0.0; }
+             fn resize(width, height) { this.x = width - 1; }
+             fn event(name, value) { if name == \"red\" { this.red = value; } }
+             fn tick(canvas, tick) { canvas.set_pixel(this.x, 0, this.red, 0, 0); }",
+        );
+
+        canvas.resize(3, 1);
+        scene.on_resize(3, 1);
+        scene.on_event(&Event {
+            name: "red".to_string(),
+            value: Some(0.2),
+        });
+        scene.on_event(&Event {
+            name: "other".to_string(),
+            value: None,
+        });
+        scene.tick(&mut canvas, &FrameTick::from_start());
+        assert!(scene.ast.is_some());
+        assert_eq!(canvas.pixels(), &[0, 0, 0, 0, 0, 0, 51, 0, 0]);
+    }
+
+    #[test]
+    fn errors_are_shown() {
+        let sources = [
+            ("syntax", "fn tick(canvas, tick) { canvas.fill(1, 1, 1) "),
+            ("missing", "fn init(width, height) {}"),
+            (
+                "runtime",
+                "fn tick(canvas, tick) { canvas.fill(\"red\", 0, 0); }",
+            ),
+            ("endless", "fn tick(canvas, tick) { loop {} }"),
+        ];
+
+        for (name, source) in sources.iter() {
+            let (mut scene, mut canvas) = scene(name, source);
+            canvas.clear_with_color(1.0, 1.0, 1.0);
+            scene.tick(&mut canvas, &FrameTick::from_start());
+            assert!(scene.ast.is_none(), "{}", name);
+            assert_eq!(&canvas.pixels()[..3], &[76, 0, 0], "{}", name);
+        }
+    }
+
+    #[test]
+    fn reloads_when_changed() {
+        let (mut scene, mut canvas) = scene("reload", "fn tick(canvas, tick) {");
+        assert!(scene.ast.is_none());
+
+        fs::write(
+            scene.watch.path(),
+            "fn tick(canvas, tick) { canvas.fill(0, 1, 0); }",
+        )
+        .unwrap();
+        // Written within the same timestamp tick on some filesystems, so
+        // make sure the watch sees a change.
+        fs::File::options()
+            .write(true)
+            .open(scene.watch.path())
+            .unwrap()
+            .set_modified(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        let mut tick = FrameTick::from_start();
+        tick.dt = 1.0;
+        scene.tick(&mut canvas, &tick);
+        assert_eq!(&canvas.pixels()[..3], &[0, 255, 0]);
+    }
+}
@@ -0,0 +1,285 @@
+use crate::grid::Grid;
+
+/// Largest radius the `filter_radius` parameter allows.
+pub const MAX_RADIUS: usize = 3;
+/// Brightness difference over which the bilateral filter stops blending,
+/// so edges stay sharp.
+const RANGE_SIGMA: f32 = 0.1;
+
+/// How the wave map is smoothed before it's drawn.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Filter {
+    None,
+    /// Removes speckles while keeping edges.
+    Median,
+    /// Plain average.
+    Box,
+    /// Weighted average, softer than the box filter.
+    Gaussian,
+    /// Gaussian average of similar neighbors only, smoothing flat areas but
+    /// not edges.
+    Bilateral,
+}
+
+impl Filter {
+    pub const ALL: [Filter; 5] = [
+        Filter::None,
+        Filter::Median,
+        Filter::Box,
+        Filter::Gaussian,
+        Filter::Bilateral,
+    ];
+}
+
+/// Runs a [`Filter`] over a map, keeping its buffers between frames.
+pub struct Smoother {
+    pub filter: Filter,
+    /// Cells out from the center that the filter looks at.
+    pub radius: usize,
+    /// Result of the first pass of separable filters.
+    temp: Grid<f32>,
+    output: Grid<f32>,
+}
+
+impl Smoother {
+    pub fn new(map: &Grid<f32>) -> Self {
+        Smoother {
+            filter: Filter::None,
+            radius: 1,
+            temp: map.clone(),
+            output: map.clone(),
+        }
+    }
+
+    /// Makes room for maps the size of `map`.
+    pub fn resize(&mut self, map: &Grid<f32>) {
+        self.temp = map.clone();
+        self.output = map.clone();
+    }
+
+    /// `map` smoothed by the filter, or `map` itself when there's none.
+    pub fn apply<'a>(&'a mut self, map: &'a Grid<f32>) -> &'a Grid<f32> {
+        let radius = self.radius.clamp(1, MAX_RADIUS) as i32;
+
+        match self.filter {
+            Filter::None => return map,
+            Filter::Median => median(map, &mut self.output, radius),
+            Filter::Box => {
+                let weights = vec![1.0; radius as usize * 2 + 1];
+                separable(map, &mut self.temp, &mut self.output, &weights);
+            }
+            Filter::Gaussian => {
+                separable(map, &mut self.temp, &mut self.output, &gaussian(radius));
+            }
+            Filter::Bilateral => bilateral(map, &mut self.output, radius),
+        }
+
+        &self.output
+    }
+}
+
+/// Normalized weights of a bell curve `radius` cells either side of the
+/// center.
+fn gaussian(radius: i32) -> Vec<f32> {
+    let sigma = radius as f32 / 2.0 + 0.5;
+    let weights: Vec<f32> = (-radius..=radius)
+        .map(|d| (-(d * d) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+
+    weights.iter().map(|weight| weight / total).collect()
+}
+
+/// Convolves `map` with `weights` across and then down, which is the same
+/// as the square kernel they multiply out to but much cheaper.
+fn separable(map: &Grid<f32>, temp: &mut Grid<f32>, output: &mut Grid<f32>, weights: &[f32]) {
+    let radius = (weights.len() / 2) as i32;
+    let total: f32 = weights.iter().sum();
+
+    temp.par_rows_mut(|y, row| {
+        for (x, cell) in row.iter_mut().enumerate() {
+            *cell = (-radius..=radius)
+                .zip(weights)
+                .map(|(d, weight)| map.sample(x as i32 + d, y as i32) * weight)
+                .sum::<f32>()
+                / total;
+        }
+    });
+
+    let temp = &*temp;
+    output.par_rows_mut(|y, row| {
+        for (x, cell) in row.iter_mut().enumerate() {
+            *cell = (-radius..=radius)
+                .zip(weights)
+                .map(|(d, weight)| temp.sample(x as i32, y as i32 + d) * weight)
+                .sum::<f32>()
+                / total;
+        }
+    });
+}
+
+fn median(map: &Grid<f32>, output: &mut Grid<f32>, radius: i32) {
+    if radius == 1 {
+        output.par_rows_mut(|y, row| {
+            for (x, cell) in row.iter_mut().enumerate() {
+                let (x, y) = (x as i32, y as i32);
+                *cell = median_of_9([
+                    map.sample(x - 1, y - 1),
+                    map.sample(x, y - 1),
+                    map.sample(x + 1, y - 1),
+                    map.sample(x - 1, y),
+                    map.sample(x, y),
+                    map.sample(x + 1, y),
+                    map.sample(x - 1, y + 1),
+                    map.sample(x, y + 1),
+                    map.sample(x + 1, y + 1),
+                ]);
+            }
+        });
+        return;
+    }
+
+    output.par_rows_mut(|y, row| {
+        // Sized for the largest window, so nothing's allocated per pixel.
+        let mut window = [0.0; (MAX_RADIUS * 2 + 1) * (MAX_RADIUS * 2 + 1)];
+
+        for (x, cell) in row.iter_mut().enumerate() {
+            let mut n = 0;
+            for v in -radius..=radius {
+                for u in -radius..=radius {
+                    window[n] = map.sample(x as i32 + u, y as i32 + v);
+                    n += 1;
+                }
+            }
+
+            let (_, median, _) = window[..n].select_nth_unstable_by(n / 2, f32::total_cmp);
+            *cell = *median;
+        }
+    });
+}
+
+/// Median of nine values with a fixed sorting network, which is much
+/// quicker than sorting them.
+fn median_of_9(mut p: [f32; 9]) -> f32 {
+    const PAIRS: [(usize, usize); 19] = [
+        (1, 2),
+        (4, 5),
+        (7, 8),
+        (0, 1),
+        (3, 4),
+        (6, 7),
+        (1, 2),
+        (4, 5),
+        (7, 8),
+        (0, 3),
+        (5, 8),
+        (4, 7),
+        (3, 6),
+        (1, 4),
+        (2, 5),
+        (4, 7),
+        (4, 2),
+        (6, 4),
+        (4, 2),
+    ];
+
+    for &(a, b) in PAIRS.iter() {
+        let (low, high) = (p[a].min(p[b]), p[a].max(p[b]));
+        p[a] = low;
+        p[b] = high;
+    }
+
+    p[4]
+}
+
+fn bilateral(map: &Grid<f32>, output: &mut Grid<f32>, radius: i32) {
+    let spatial = gaussian(radius);
+
+    output.par_rows_mut(|y, row| {
+        for (x, cell) in row.iter_mut().enumerate() {
+            let center = map[(x, y)];
+            let mut total = 0.0;
+            let mut weights = 0.0;
+
+            for (v, spatial_v) in (-radius..=radius).zip(&spatial) {
+                for (u, spatial_u) in (-radius..=radius).zip(&spatial) {
+                    let value = map.sample(x as i32 + u, y as i32 + v);
+                    let difference = (value - center) / RANGE_SIGMA;
+                    let weight = spatial_u * spatial_v * (-difference * difference / 2.0).exp();
+
+                    total += value * weight;
+                    weights += weight;
+                }
+            }
+
+            *cell = total / weights;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Boundary;
+
+    /// A dark left half and bright right half, with one bright speck.
+    fn edge() -> Grid<f32> {
+        let mut map = Grid::from_fn(8, 6, |x, _| if x < 4 { 0.2 } else { 0.8 })
+            .with_boundary(Boundary::Clamp);
+        map[(1, 2)] = 1.0;
+        map
+    }
+
+    fn smooth(filter: Filter, map: &Grid<f32>) -> Grid<f32> {
+        let mut smoother = Smoother::new(map);
+        smoother.filter = filter;
+        smoother.apply(map).clone()
+    }
+
+    #[test]
+    fn none_passes_through() {
+        let map = edge();
+        assert_eq!(
+            smooth(Filter::None, &map).iter().collect::<Vec<_>>(),
+            map.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn median_removes_specks_and_keeps_edges() {
+        let filtered = smooth(Filter::Median, &edge());
+        assert_eq!(filtered[(1, 2)], 0.2);
+        assert_eq!(filtered[(3, 2)], 0.2);
+        assert_eq!(filtered[(4, 2)], 0.8);
+    }
+
+    #[test]
+    fn median_of_9_matches_sorting() {
+        let mut values = [0.7, 0.1, 0.9, 0.3, 0.5, 0.8, 0.2, 0.6, 0.4];
+        for shift in 0..9 {
+            values.rotate_left(1);
+            values.swap(shift % 9, (shift * 4) % 9);
+            assert_eq!(median_of_9(values), 0.5);
+        }
+    }
+
+    #[test]
+    fn averages_keep_flat_areas_and_blur_edges() {
+        for filter in [Filter::Box, Filter::Gaussian] {
+            let filtered = smooth(filter, &edge());
+            assert!((filtered[(6, 3)] - 0.8).abs() < 1e-6, "{:?}", filter);
+            assert!(
+                filtered[(3, 3)] > 0.2 && filtered[(4, 3)] < 0.8,
+                "{:?}",
+                filter
+            );
+        }
+    }
+
+    #[test]
+    fn bilateral_keeps_edges() {
+        let filtered = smooth(Filter::Bilateral, &edge());
+        assert!((filtered[(3, 4)] - 0.2).abs() < 1e-3);
+        assert!((filtered[(4, 4)] - 0.8).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,179 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// How a kernel's weights fall off away from the center.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Shape {
+    /// `(1 / d²)^(0.1 * spread)`, nearly flat with a slight pull towards
+    /// the center.
+    Falloff,
+    /// A bell curve `spread` cells wide.
+    Gaussian,
+    /// A band `spread` cells wide at the edge of the kernel, ignoring the
+    /// cells close by.
+    Ring,
+    /// A bell curve along the axes only.
+    Cross,
+    /// A matrix loaded with [`Kernel::load`].
+    Custom,
+}
+
+impl Shape {
+    pub const ALL: [Shape; 5] = [
+        Shape::Falloff,
+        Shape::Gaussian,
+        Shape::Ring,
+        Shape::Cross,
+        Shape::Custom,
+    ];
+}
+
+/// Weights given to each neighbor when a cell grows, leaving out the center
+/// and anything weighted zero.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    offsets: Vec<((i32, i32), f32)>,
+}
+
+impl Kernel {
+    /// A square kernel reaching `radius` cells out, shaped by `shape`.
+    /// [`Shape::Custom`] falls back to [`Shape::Falloff`], since it has no
+    /// matrix of its own.
+    pub fn new(shape: Shape, radius: i32, spread: f32) -> Self {
+        let spread = spread.max(0.01);
+        let bell = |d: f32| (-d * d / (2.0 * spread * spread)).exp();
+
+        Kernel::from_fn(radius, |dx, dy| {
+            let d = ((dx * dx + dy * dy) as f32).sqrt();
+            match shape {
+                Shape::Falloff | Shape::Custom => (1.0 / (d * d)).powf(0.1 * spread),
+                Shape::Gaussian => bell(d),
+                Shape::Ring => bell(d - radius as f32),
+                Shape::Cross if dx == 0 || dy == 0 => bell(d),
+                Shape::Cross => 0.0,
+            }
+        })
+    }
+
+    fn from_fn<F: Fn(i32, i32) -> f32>(radius: i32, weight: F) -> Self {
+        let mut offsets = vec![];
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let weight = weight(dx, dy);
+                if (dx, dy) != (0, 0) && weight > 0.0 {
+                    offsets.push(((dx, dy), weight));
+                }
+            }
+        }
+
+        Kernel { offsets }
+    }
+
+    /// Neighbor offsets and their weights.
+    pub fn offsets(&self) -> &[((i32, i32), f32)] {
+        &self.offsets
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Kernel> {
+        Kernel::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses a kernel file: a square matrix of weights with an odd number
+    /// of rows, one row per line. The center weight is ignored, and `#`
+    /// begins a comment.
+    ///
+    /// ```text
+    /// # A diamond
+    /// 0 0 1 0 0
+    /// 0 1 1 1 0
+    /// 1 1 0 1 1
+    /// 0 1 1 1 0
+    /// 0 0 1 0 0
+    /// ```
+    pub fn parse(text: &str) -> io::Result<Kernel> {
+        let mut rows = vec![];
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let row = line
+                .split_whitespace()
+                .map(|word| word.parse::<f32>().ok().filter(|weight| weight.is_finite()))
+                .collect::<Option<Vec<f32>>>()
+                .ok_or_else(|| invalid(format!("line {}: can't parse {:?}", n + 1, line.trim())))?;
+
+            if !row.is_empty() {
+                rows.push(row);
+            }
+        }
+
+        let size = rows.len();
+        if size % 2 == 0 || rows.iter().any(|row| row.len() != size) {
+            return Err(invalid(format!(
+                "expected a square matrix with an odd number of rows, got {} rows",
+                size
+            )));
+        }
+
+        let radius = (size / 2) as i32;
+        let kernel = Kernel::from_fn(radius, |dx, dy| {
+            rows[(dy + radius) as usize][(dx + radius) as usize]
+        });
+
+        // Cells would have nothing to grow from.
+        if kernel.offsets.is_empty() {
+            return Err(invalid(
+                "kernel has no positive weights besides the center".to_string(),
+            ));
+        }
+
+        Ok(kernel)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        Kernel::parse(text).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn parses_kernels() {
+        let kernel = Kernel::parse("# a plus\n0 1 0\n2 9 0.5\n0 -1 0\n").unwrap();
+        assert_eq!(
+            kernel.offsets(),
+            [((0, -1), 1.0), ((-1, 0), 2.0), ((1, 0), 0.5)]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_kernels() {
+        assert_eq!(
+            error("1 1\n1 1\n"),
+            "expected a square matrix with an odd number of rows, got 2 rows"
+        );
+        assert_eq!(
+            error("1 1 1\n1 1\n1 1 1\n"),
+            "expected a square matrix with an odd number of rows, got 3 rows"
+        );
+        assert_eq!(error("1 x 1\n"), "line 1: can't parse \"1 x 1\"");
+        assert_eq!(
+            error("0 inf 0\n0 0 0\n0 0 0\n"),
+            "line 1: can't parse \"0 inf 0\""
+        );
+        assert_eq!(
+            error("5\n"),
+            "kernel has no positive weights besides the center"
+        );
+        assert_eq!(
+            error("0 0 0\n0 1 0\n0 -2 0\n"),
+            "kernel has no positive weights besides the center"
+        );
+    }
+}
@@ -0,0 +1,505 @@
+mod filter;
+mod kernel;
+
+use palette::{FromColor, Oklch, Srgb};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::control::Disturbance;
+use crate::gradient::Palette;
+use crate::grid::{Boundary, DoubleBuffer, Grid};
+use crate::params::{palette_from_param, palette_param, Param};
+use crate::{Canvas, FrameTick, Scene};
+
+use filter::{Filter, Smoother};
+pub use kernel::{Kernel, Shape};
+
+/// Largest kernel radius the `radius` parameter allows.
+const MAX_RADIUS: i32 = 5;
+/// Radius of a drop, in cells.
+const DROP_RADIUS: f32 = 2.5;
+/// Half the width of a stroke, in cells.
+const LINE_RADIUS: f32 = 1.0;
+
+/// A named set of automaton parameters.
+struct Preset {
+    name: &'static str,
+    shape: Shape,
+    radius: i32,
+    spread: f32,
+    threshold: f32,
+    decay: f32,
+}
+
+const PRESETS: [Preset; 4] = [
+    // The original look: broad, slowly drifting waves.
+    Preset {
+        name: "waves",
+        shape: Shape::Falloff,
+        radius: 2,
+        spread: 1.0,
+        threshold: 0.5,
+        decay: 0.3,
+    },
+    // Large soft blobs drifting across the canvas.
+    Preset {
+        name: "blobs",
+        shape: Shape::Gaussian,
+        radius: 3,
+        spread: 3.0,
+        threshold: 0.5,
+        decay: 0.6,
+    },
+    // Bands that keep pushing out from bright spots, since cells only see
+    // neighbors a few steps away.
+    Preset {
+        name: "ripples",
+        shape: Shape::Ring,
+        radius: 4,
+        spread: 2.0,
+        threshold: 0.5,
+        decay: 1.2,
+    },
+    // A fine, flickering texture fed only from along the axes.
+    Preset {
+        name: "shimmer",
+        shape: Shape::Cross,
+        radius: 3,
+        spread: 2.0,
+        threshold: 0.7,
+        decay: 0.6,
+    },
+];
+
+pub struct WaveScene {
+    map: DoubleBuffer<f32>,
+
+    /// Weights of the neighbors a cell grows from, built from the
+    /// parameters below.
+    kernel: Kernel,
+    /// Kernel used for [`Shape::Custom`].
+    custom: Option<Kernel>,
+    /// Last preset chosen. The other parameters can be changed from it.
+    preset: usize,
+    shape: Shape,
+    radius: i32,
+    spread: f32,
+    /// Brightness a neighbor needs to count towards a cell's growth, give or
+    /// take 0.1.
+    threshold: f32,
+    /// Share of its brightness a cell loses per second, give or take 0.1.
+    decay: f32,
+
+    smoother: Smoother,
+
+    /// Random drops per second.
+    rain: f32,
+    /// Drops owed from previous frames, so light rain still falls.
+    rain_carry: f32,
+    /// Whether audio onsets make drops.
+    onset_drops: bool,
+
+    palette: Option<Palette>,
+    palette_index: usize,
+    hue_speed: f32,
+    rng: StdRng,
+}
+
+impl WaveScene {
+    pub fn new(canvas: &Canvas) -> Self {
+        Self::seeded(canvas, rand::thread_rng().gen())
+    }
+
+    /// A scene that plays out the same way each time for the same `seed`
+    /// and frame times, with or without the `parallel` feature.
+    pub fn seeded(canvas: &Canvas, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let map = Grid::from_fn(canvas.width as usize, canvas.height as usize, |_, _| {
+            rng.gen()
+        })
+        .with_boundary(Boundary::Wrap);
+
+        let preset = &PRESETS[0];
+
+        WaveScene {
+            smoother: Smoother::new(&map),
+            rain: 0.0,
+            rain_carry: 0.0,
+            onset_drops: true,
+            map: DoubleBuffer::new(map),
+            kernel: Kernel::new(preset.shape, preset.radius, preset.spread),
+            custom: None,
+            preset: 0,
+            shape: preset.shape,
+            radius: preset.radius,
+            spread: preset.spread,
+            threshold: preset.threshold,
+            decay: preset.decay,
+            palette: None,
+            palette_index: 0,
+            hue_speed: 0.1,
+            rng,
+        }
+    }
+
+    /// Colors the map through `palette` instead of the default Oklch hue
+    /// rotation.
+    pub fn with_palette(mut self, palette: Palette) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+
+    /// Names of the presets, in `preset` parameter order.
+    pub fn preset_names() -> Vec<&'static str> {
+        PRESETS.iter().map(|preset| preset.name).collect()
+    }
+
+    /// Starts from the preset called `name`, if there is one.
+    pub fn with_preset(mut self, name: &str) -> Option<Self> {
+        let index = PRESETS.iter().position(|preset| preset.name == name)?;
+        self.apply_preset(index);
+        Some(self)
+    }
+
+    /// Grows cells from `kernel`'s weights, until another kernel shape is
+    /// chosen.
+    pub fn with_kernel(mut self, kernel: Kernel) -> Self {
+        self.custom = Some(kernel);
+        self.shape = Shape::Custom;
+        self.rebuild_kernel();
+        self
+    }
+
+    fn apply_preset(&mut self, index: usize) {
+        let preset = &PRESETS[index];
+        self.preset = index;
+        self.shape = preset.shape;
+        self.radius = preset.radius;
+        self.spread = preset.spread;
+        self.threshold = preset.threshold;
+        self.decay = preset.decay;
+        self.rebuild_kernel();
+    }
+
+    fn rebuild_kernel(&mut self) {
+        self.kernel = match (self.shape, &self.custom) {
+            (Shape::Custom, Some(custom)) => custom.clone(),
+            (shape, _) => Kernel::new(shape, self.radius, self.spread),
+        };
+    }
+
+    /// Brightens the cells within `radius` of (`x`, `y`), in cells, to at
+    /// least `strength`, less so towards the edge.
+    fn splash(&mut self, x: f32, y: f32, radius: f32, strength: f32) {
+        let map = self.map.front_mut();
+        let reach = radius.ceil() as i32;
+        let (cx, cy) = (x.round() as i32, y.round() as i32);
+
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                if distance > radius || !map.in_bounds(cx + dx, cy + dy) {
+                    continue;
+                }
+
+                let cell = &mut map[((cx + dx) as usize, (cy + dy) as usize)];
+                let value = strength * (1.0 - distance / (radius + 1.0));
+                *cell = cell.max(value).clamp(0.0, 1.0);
+            }
+        }
+    }
+
+    /// A drop somewhere random.
+    fn random_drop(&mut self, strength: f32) {
+        let map = self.map.front();
+        if map.is_empty() {
+            return;
+        }
+        let x = self.rng.gen_range(0.0..map.width() as f32);
+        let y = self.rng.gen_range(0.0..map.height() as f32);
+        self.splash(x, y, DROP_RADIUS, strength);
+    }
+
+    fn draw_map(&mut self, canvas: &mut Canvas, t: f32) {
+        let palette = &self.palette;
+        let hue_speed = self.hue_speed;
+        let map = self.smoother.apply(self.map.front());
+
+        canvas.par_rows_mut(|y, row| {
+            for x in 0..map.width() {
+                let value = map[(x, y as usize)].powf(2.0);
+
+                if let Some(palette) = palette {
+                    let (r, g, b) = palette.sample(value);
+                    row.set_pixel(x as u32, r, g, b);
+                    continue;
+                }
+
+                let hsv = Oklch::new(value.powf(1.0), 0.1, (value + t * hue_speed) * 360.0);
+                let rgb = Srgb::from_color(hsv);
+
+                row.set_pixel(x as u32, rgb.red, rgb.green, rgb.blue);
+            }
+        });
+    }
+}
+
+fn grow_step<R: Rng>(
+    x: i32,
+    y: i32,
+    map: &Grid<f32>,
+    kernel: &Kernel,
+    threshold: f32,
+    rng: &mut R,
+) -> f32 {
+    let mut val = map.sample(x, y);
+
+    let mut n = 0.0;
+    let mut c = 0.0;
+
+    for &((u, v), weight) in kernel.offsets() {
+        let last_value2 = map.sample(x + u, y + v);
+
+        if last_value2 > rng.gen_range(threshold - 0.1..threshold + 0.1) {
+            c += last_value2 * rng.gen_range(0.9..1.1) * weight;
+            n += weight;
+        }
+    }
+
+    // Nothing to grow from, as when no neighbor passed the threshold.
+    if n == 0.0 {
+        return val;
+    }
+
+    val = (val + c) / n;
+    val.clamp(0.0, 1.0)
+}
+
+impl Scene for WaveScene {
+    fn name(&self) -> &str {
+        "wave"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![
+            palette_param(self.palette_index),
+            Param::new("hue_speed", 0.0, 1.0, self.hue_speed),
+            Param::new(
+                "preset",
+                0.0,
+                (PRESETS.len() - 1) as f32,
+                self.preset as f32,
+            ),
+            Param::new(
+                "kernel",
+                0.0,
+                (Shape::ALL.len() - 1) as f32,
+                Shape::ALL
+                    .iter()
+                    .position(|&shape| shape == self.shape)
+                    .unwrap() as f32,
+            ),
+            Param::new("radius", 1.0, MAX_RADIUS as f32, self.radius as f32),
+            Param::new("spread", 0.1, 4.0, self.spread),
+            Param::new("threshold", 0.1, 0.9, self.threshold),
+            Param::new("decay", 0.0, 2.0, self.decay),
+            Param::new(
+                "filter",
+                0.0,
+                (Filter::ALL.len() - 1) as f32,
+                Filter::ALL
+                    .iter()
+                    .position(|&filter| filter == self.smoother.filter)
+                    .unwrap() as f32,
+            ),
+            Param::new(
+                "filter_radius",
+                1.0,
+                filter::MAX_RADIUS as f32,
+                self.smoother.radius as f32,
+            ),
+            Param::new("rain", 0.0, 20.0, self.rain),
+            Param::new("onset_drops", 0.0, 1.0, self.onset_drops as u8 as f32),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) {
+        match name {
+            "palette" => {
+                let (index, palette) = palette_from_param(value);
+                self.palette_index = index;
+                self.palette = palette;
+            }
+            "hue_speed" => self.hue_speed = value,
+            "preset" => {
+                self.apply_preset((value.round().max(0.0) as usize).min(PRESETS.len() - 1));
+            }
+            "kernel" => {
+                let index = (value.round().max(0.0) as usize).min(Shape::ALL.len() - 1);
+                self.shape = Shape::ALL[index];
+                self.rebuild_kernel();
+            }
+            "radius" => {
+                self.radius = (value.round() as i32).clamp(1, MAX_RADIUS);
+                self.rebuild_kernel();
+            }
+            "spread" => {
+                self.spread = value;
+                self.rebuild_kernel();
+            }
+            "threshold" => self.threshold = value,
+            "decay" => self.decay = value,
+            "rain" => self.rain = value,
+            "onset_drops" => self.onset_drops = value >= 0.5,
+            "filter" => {
+                let index = (value.round().max(0.0) as usize).min(Filter::ALL.len() - 1);
+                self.smoother.filter = Filter::ALL[index];
+            }
+            "filter_radius" => {
+                self.smoother.radius = (value.round() as usize).clamp(1, filter::MAX_RADIUS);
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, canvas: &mut Canvas, tick: &FrameTick) {
+        self.rain_carry += self.rain * tick.dt;
+        while self.rain_carry >= 1.0 {
+            self.rain_carry -= 1.0;
+            let strength = self.rng.gen_range(0.6..1.0);
+            self.random_drop(strength);
+        }
+
+        if self.onset_drops && tick.audio.onset {
+            self.random_drop((0.5 + tick.audio.level).min(1.0));
+        }
+
+        // Each row draws from its own generator, seeded from the scene's, so
+        // the result doesn't depend on which thread runs which row.
+        let seed: u64 = self.rng.gen();
+        let kernel = &self.kernel;
+        let (threshold, decay) = (self.threshold, self.decay);
+        let (last_map, map) = self.map.split();
+
+        map.par_rows_mut(|y, row| {
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(y as u64));
+
+            for (x, cell) in row.iter_mut().enumerate() {
+                let last_value = last_map[(x, y)];
+
+                let decay = rng.gen_range((decay - 0.1).max(0.0)..decay + 0.1);
+                let mut value = last_value * (1.0 - decay * tick.dt);
+
+                if last_value <= rng.gen_range(0.1..0.35) {
+                    value = grow_step(x as i32, y as i32, last_map, kernel, threshold, &mut rng);
+                }
+
+                *cell = value.clamp(0.0, 1.0);
+            }
+        });
+
+        self.map.swap();
+        self.draw_map(canvas, tick.t);
+    }
+
+    /// Stretches the map over the new canvas, so the pattern carries on, or
+    /// starts a fresh one if there was nothing to stretch.
+    fn on_resize(&mut self, width: u32, height: u32) {
+        let (width, height) = (width as usize, height as usize);
+        let map = if self.map.front().is_empty() {
+            let rng = &mut self.rng;
+            Grid::from_fn(width, height, |_, _| rng.gen()).with_boundary(Boundary::Wrap)
+        } else {
+            self.map.front().resized(width, height)
+        };
+        self.smoother.resize(&map);
+        self.map = DoubleBuffer::new(map);
+    }
+
+    fn disturb(&mut self, disturbance: &Disturbance) {
+        let map = self.map.front();
+        if map.is_empty() {
+            return;
+        }
+        let (width, height) = ((map.width() - 1) as f32, (map.height() - 1) as f32);
+        let to_cells = |(x, y): (f32, f32)| (x * width, y * height);
+
+        match *disturbance {
+            Disturbance::Point { x, y, strength } => {
+                let (x, y) = to_cells((x, y));
+                self.splash(x, y, DROP_RADIUS, strength);
+            }
+            Disturbance::Line { from, to, strength } => {
+                let (from, to) = (to_cells(from), to_cells(to));
+                let length = (to.0 - from.0).abs().max((to.1 - from.1).abs());
+                let steps = length.ceil().max(1.0) as usize;
+
+                for i in 0..=steps {
+                    let t = i as f32 / steps as f32;
+                    let x = from.0 + (to.0 - from.0) * t;
+                    let y = from.1 + (to.1 - from.1) * t;
+                    self.splash(x, y, LINE_RADIUS, strength);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(seed: u64) -> Vec<u8> {
+        let mut canvas = Canvas::new(40, 24);
+        let mut scene = WaveScene::seeded(&canvas, seed);
+
+        let mut tick = FrameTick::from_start();
+        tick.dt = 1.0 / 30.0;
+        for frame in 0..20 {
+            tick.t = frame as f32 * tick.dt;
+            scene.tick(&mut canvas, &tick);
+        }
+
+        canvas.pixels().to_vec()
+    }
+
+    #[test]
+    fn seeded_scenes_repeat() {
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn cells_without_neighbors_keep_their_value() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut map = Grid::new(3, 3, 0.0);
+        map[(1, 1)] = 0.4;
+
+        let empty = Kernel::new(Shape::Cross, 0, 1.0);
+        assert!(empty.offsets().is_empty());
+        assert_eq!(grow_step(1, 1, &map, &empty, 0.5, &mut rng), 0.4);
+
+        // Neighbors all below the threshold count for nothing either.
+        let kernel = Kernel::new(Shape::Gaussian, 1, 1.0);
+        assert_eq!(grow_step(1, 1, &map, &kernel, 0.9, &mut rng), 0.4);
+    }
+
+    #[test]
+    fn draws_after_resizing() {
+        let mut canvas = Canvas::new(40, 24);
+        let mut scene = WaveScene::seeded(&canvas, 7);
+        let tick = FrameTick::from_start();
+        scene.tick(&mut canvas, &tick);
+
+        for &(width, height) in &[(64, 32), (8, 48), (1, 1)] {
+            canvas.resize(width, height);
+            scene.on_resize(width, height);
+            scene.tick(&mut canvas, &tick);
+            scene.disturb(&Disturbance::Point {
+                x: 1.0,
+                y: 1.0,
+                strength: 1.0,
+            });
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Seconds between checks of the file.
+const CHECK_INTERVAL: f32 = 0.5;
+
+/// Notices when a file changes, by looking at its modification time every
+/// so often.
+pub struct FileWatch {
+    path: PathBuf,
+    /// When the file was last changed, as of the last check.
+    modified: Option<SystemTime>,
+    since_check: f32,
+}
+
+impl FileWatch {
+    /// Watches `path`, taking what's there now as already seen.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+
+        FileWatch {
+            modified: modified(&path),
+            path,
+            since_check: 0.0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file changed since this last returned true. Call it
+    /// every frame with the frame's `dt`; the file is only looked at every
+    /// [`CHECK_INTERVAL`].
+    pub fn changed(&mut self, dt: f32) -> bool {
+        self.since_check += dt;
+        if self.since_check < CHECK_INTERVAL {
+            return false;
+        }
+        self.since_check = 0.0;
+
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}